@@ -0,0 +1,135 @@
+//! Throughput of the request parser, compared with a baseline.
+//!
+//! The `baseline` cases run the head scan the parser used before scanning incrementally: on
+//! every read, the whole buffer is searched again for the empty line, with a lossy UTF-8
+//! conversion per byte, and checked for bare line endings. The head is then parsed with the
+//! current `Request::parse`, so the comparison measures the scan only. The requests have no
+//! body.
+//!
+//! Run with `cargo bench --bench parser`.
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use webserv_rs::config::Limits;
+use webserv_rs::parser::RequestParser;
+use webserv_rs::request::Request;
+
+const READ_SIZE: usize = 1024;
+
+fn get_request(headers: usize, value_size: usize) -> Vec<u8> {
+    let mut request = b"GET /api/users/42?verbose=1 HTTP/1.1\r\nHost: localhost:8080\r\n".to_vec();
+    let value = "v".repeat(value_size);
+    for i in 0..headers {
+        request.extend_from_slice(format!("X-Header-{i}: {value}\r\n").as_bytes());
+    }
+    request.extend_from_slice(b"\r\n");
+    request
+}
+
+// Feed the data in reads of `READ_SIZE` bytes, as the worker does, and parse every request.
+fn parse_all(data: &[u8], expected: usize) {
+    let mut parser = RequestParser::new();
+    let mut parsed = 0;
+    for read in data.chunks(READ_SIZE) {
+        parser.feed(read);
+        while let Some(request) = parser.parse().unwrap() {
+            black_box(request);
+            parsed += 1;
+        }
+    }
+    assert_eq!(parsed, expected);
+}
+
+// Request heads found by rescanning the buffer on every read.
+struct BaselineParser {
+    buffer: Vec<u8>,
+    limits: Limits,
+}
+
+impl BaselineParser {
+    fn parse(&mut self) -> Option<Request> {
+        self.check_request_line();
+        let Some(index) = get_double_crcn_index(&self.buffer) else {
+            assert!(self.buffer.len() <= self.limits.max_header_size);
+            assert!(!has_bare_line_ending(&self.buffer));
+            return None;
+        };
+        let head = &self.buffer[..index];
+        assert!(head.len() <= self.limits.max_header_size);
+        assert!(head.windows(2).filter(|pair| pair == b"\r\n").count() <= self.limits.max_headers);
+        assert!(!has_bare_line_ending(&self.buffer[..index + 4]));
+        let request = Request::parse(&String::from_utf8_lossy(&self.buffer[..index])).unwrap();
+        self.buffer.drain(..index + 4);
+        Some(request)
+    }
+
+    fn check_request_line(&self) {
+        let line_end = self
+            .buffer
+            .iter()
+            .position(|&byte| byte == b'\r' || byte == b'\n')
+            .unwrap_or(self.buffer.len());
+        let target = self.buffer[..line_end].split(|&byte| byte == b' ').nth(1);
+        assert!(target.is_none_or(|target| target.len() <= self.limits.max_uri_length));
+    }
+}
+
+fn has_bare_line_ending(buffer: &[u8]) -> bool {
+    buffer.iter().enumerate().any(|(i, &byte)| match byte {
+        b'\n' => i == 0 || buffer[i - 1] != b'\r',
+        b'\r' => buffer.get(i + 1).is_some_and(|&next| next != b'\n'),
+        _ => false,
+    })
+}
+
+fn get_double_crcn_index(buffer: &[u8]) -> Option<usize> {
+    for (i, _) in buffer.iter().enumerate() {
+        if i + 3 < buffer.len() {
+            if String::from_utf8_lossy(&buffer[i..i + 4]) == "\r\n\r\n" {
+                return Some(i);
+            }
+        } else {
+            break;
+        }
+    }
+    None
+}
+
+fn parse_all_baseline(data: &[u8], expected: usize) {
+    let mut parser = BaselineParser {
+        buffer: Vec::new(),
+        limits: Limits::default(),
+    };
+    let mut parsed = 0;
+    for read in data.chunks(READ_SIZE) {
+        parser.buffer.extend_from_slice(read);
+        while let Some(request) = parser.parse() {
+            black_box(request);
+            parsed += 1;
+        }
+    }
+    assert_eq!(parsed, expected);
+}
+
+fn bench_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser");
+
+    let pipelined = get_request(10, 24).repeat(100);
+    group.throughput(Throughput::Bytes(pipelined.len() as u64));
+    group.bench_function("pipelined_requests", |b| {
+        b.iter(|| parse_all(&pipelined, 100))
+    });
+    group.bench_function("pipelined_requests_baseline", |b| {
+        b.iter(|| parse_all_baseline(&pipelined, 100))
+    });
+
+    let large = get_request(90, 150);
+    group.throughput(Throughput::Bytes(large.len() as u64));
+    group.bench_function("large_header_block", |b| b.iter(|| parse_all(&large, 1)));
+    group.bench_function("large_header_block_baseline", |b| {
+        b.iter(|| parse_all_baseline(&large, 1))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parser);
+criterion_main!(benches);
@@ -0,0 +1,362 @@
+//! Request body module
+//!
+//! By default the worker reads the whole body of a request, up to the `max_body_size` of the
+//! [Limits](crate::config::Limits), before calling the handler. Once body streaming is
+//! enabled with `HttpServer::set_body_streaming`, the handler is called as soon as the header
+//! block is received and the body is read from the connection on demand through
+//! [Request::body_reader](crate::request::Request::body_reader).
+//! The [Body] reader removes the chunked framing and decompresses the transfer codings, and
+//! is not limited in size. A body sent with a content or transfer coding other than `chunked`
+//! is limited to `max_body_size` once decoded: past it, the reader fails with an error
+//! wrapping a `413 Content Too Large`, and the worker answers `413` and closes the
+//! connection whatever the handler returns.
+//!
+//! The bytes of the body left unread by the handler are drained once it returns, so that the
+//! next request on the connection can be read. When more than [MAX_DRAIN_SIZE] bytes are
+//! left, or when the client waits for a `100 Continue` that was never sent because the
+//! handler did not read the body, the connection is closed instead.
+//!
+//! The event loop always buffers the bodies.
+//!
+//! # Example
+//! ```rust,no_run
+//! use std::io::Read;
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::http_server::HttpServer;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn upload(mut request: Request) -> Response {
+//!     let mut file = std::fs::File::create("/tmp/upload").unwrap();
+//!     match std::io::copy(&mut request.body_reader(), &mut file) {
+//!         Ok(size) => Response::new(200, format!("{size}").into_bytes(), vec![], ContentType::Text),
+//!         Err(_) => Response::new(400, vec![], vec![], ContentType::Text),
+//!     }
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let mut server = HttpServer::new("127.0.0.1", 8080)?;
+//!     server.set_body_streaming(true);
+//!     server.run(upload)
+//! }
+//! ```
+use crate::header_map::HeaderMap;
+use crate::http_error::HttpError;
+use crate::interim::CONTINUE;
+use crate::parser::BodyFraming;
+use crate::socket::Socket;
+use std::fmt;
+use std::io::{Cursor, Error, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Maximum number of unread body bytes drained after the handler before the connection is
+/// closed instead.
+pub const MAX_DRAIN_SIZE: usize = 1024 * 1024;
+const READ_SIZE: usize = 4096;
+
+/// Body of a request, buffered or read from the connection on demand.
+pub struct Body {
+    inner: BodyInner,
+    state: Option<Arc<Mutex<BodyState>>>,
+}
+
+enum BodyInner {
+    Buffered(Cursor<Vec<u8>>),
+    Stream(Box<dyn Read + Send>),
+}
+
+impl Body {
+    pub fn buffered(body: Vec<u8>) -> Self {
+        Self {
+            inner: BodyInner::Buffered(Cursor::new(body)),
+            state: None,
+        }
+    }
+
+    pub(crate) fn stream(reader: Box<dyn Read + Send>, state: &Arc<Mutex<BodyState>>) -> Self {
+        Self {
+            inner: BodyInner::Stream(reader),
+            state: Some(Arc::clone(state)),
+        }
+    }
+
+    /// Trailer fields sent after a chunked body, available once the body was read to the
+    /// end. Always empty for a buffered body, whose trailers are in
+    /// [Request::trailers](crate::request::Request::trailers).
+    pub fn trailers(&self) -> HeaderMap {
+        let Some(state) = self.state.as_ref() else {
+            return HeaderMap::new();
+        };
+        let state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        match &state.framing {
+            BodyFraming::Chunked(chunk_handler) if state.finished => chunk_handler.trailers.clone(),
+            _ => HeaderMap::new(),
+        }
+    }
+}
+
+impl Read for Body {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            BodyInner::Buffered(cursor) => cursor.read(buf),
+            BodyInner::Stream(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.inner {
+            BodyInner::Buffered(cursor) => write!(f, "Body({} bytes)", cursor.get_ref().len()),
+            BodyInner::Stream(_) => write!(f, "Body(stream)"),
+        }
+    }
+}
+
+/// Socket shared between the worker and the body reader given to the handler.
+pub(crate) struct SharedSocket<T>(Arc<Mutex<T>>);
+
+impl<T: Socket + Send + 'static> SharedSocket<T> {
+    pub(crate) fn new(socket: T) -> Self {
+        Self(Arc::new(Mutex::new(socket)))
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Handle on the socket for the readers and writers given to the handler.
+    pub(crate) fn handle(&self) -> Arc<Mutex<dyn Socket + Send>> {
+        Arc::clone(&self.0) as Arc<Mutex<dyn Socket + Send>>
+    }
+}
+
+impl<T: Socket + Send + 'static> Read for SharedSocket<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.lock().read(buf)
+    }
+}
+
+impl<T: Socket + Send + 'static> Write for SharedSocket<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.lock().write_all(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+impl<T: Socket + Send + 'static> Socket for SharedSocket<T> {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.lock().set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.lock().set_write_timeout(timeout)
+    }
+}
+
+/// Progress of a streamed body, shared between the worker and the reader.
+pub(crate) struct BodyState {
+    framing: BodyFraming,
+    // Bytes received from the socket and not decoded yet.
+    input: Vec<u8>,
+    // Decoded bytes not given to the reader yet.
+    output: Vec<u8>,
+    finished: bool,
+    // The client waits for a `100 Continue` before sending the body.
+    expect_continue: bool,
+    // The decoded body went past its limit.
+    too_large: bool,
+}
+
+impl BodyState {
+    pub(crate) fn new(
+        framing: BodyFraming,
+        input: Vec<u8>,
+        expect_continue: bool,
+    ) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            framing,
+            input,
+            output: Vec::new(),
+            finished: false,
+            expect_continue,
+            too_large: false,
+        }))
+    }
+
+    /// True when the decoded body went past its limit, see [LimitedBody].
+    pub(crate) fn is_too_large(&self) -> bool {
+        self.too_large
+    }
+
+    fn read(&mut self, socket: &mut dyn Read, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if !self.output.is_empty() {
+                let n = buf.len().min(self.output.len());
+                buf[..n].copy_from_slice(&self.output[..n]);
+                self.output.drain(..n);
+                return Ok(n);
+            }
+            if self.finished || buf.is_empty() {
+                return Ok(0);
+            }
+            if let BodyFraming::Length(remaining) = &mut self.framing {
+                if *remaining == 0 {
+                    self.finished = true;
+                    return Ok(0);
+                }
+                let max = buf.len().min(*remaining);
+                let n = match self.input.is_empty() {
+                    true => socket.read(&mut buf[..max])?,
+                    false => {
+                        let n = max.min(self.input.len());
+                        buf[..n].copy_from_slice(&self.input[..n]);
+                        self.input.drain(..n);
+                        n
+                    }
+                };
+                if n == 0 {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                *remaining -= n;
+                return Ok(n);
+            }
+            if self.input.is_empty() {
+                let mut tmp = [0u8; READ_SIZE];
+                let n = socket.read(&mut tmp)?;
+                if n == 0 {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                self.input.extend_from_slice(&tmp[..n]);
+            }
+            let BodyFraming::Chunked(chunk_handler) = &mut self.framing else {
+                continue;
+            };
+            let input = std::mem::take(&mut self.input);
+            chunk_handler
+                .parse_chunks(&input)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+            self.output = std::mem::take(&mut chunk_handler.body);
+            if chunk_handler.is_body_ready() {
+                self.finished = true;
+                self.input = std::mem::take(&mut chunk_handler.leftover);
+            }
+        }
+    }
+
+    /// Read and discard the rest of the body, up to `limit` bytes. Return the bytes received
+    /// after the body, or `None` when the body is longer than the limit, unreadable or not
+    /// sent by the client.
+    pub(crate) fn drain(&mut self, socket: &mut dyn Read, limit: usize) -> Option<Vec<u8>> {
+        if self.expect_continue || self.too_large {
+            return None;
+        }
+        let mut drained = 0;
+        let mut tmp = [0u8; READ_SIZE];
+        while !self.finished || !self.output.is_empty() {
+            let n = self.read(socket, &mut tmp).ok()?;
+            drained += n;
+            if n == 0 && !self.finished || drained > limit {
+                return None;
+            }
+        }
+        Some(std::mem::take(&mut self.input))
+    }
+}
+
+/// Reader of the raw body, without the transfer codings decoded.
+pub(crate) struct RawBody {
+    state: Arc<Mutex<BodyState>>,
+    socket: Arc<Mutex<dyn Socket + Send>>,
+}
+
+impl RawBody {
+    pub(crate) fn new(
+        state: &Arc<Mutex<BodyState>>,
+        socket: Arc<Mutex<dyn Socket + Send>>,
+    ) -> Self {
+        Self {
+            state: Arc::clone(state),
+            socket,
+        }
+    }
+}
+
+impl Read for RawBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut socket = self.socket.lock().unwrap_or_else(PoisonError::into_inner);
+        if std::mem::take(&mut state.expect_continue) {
+            socket.write_all(CONTINUE)?;
+            socket.flush()?;
+        }
+        state.read(&mut *socket, buf)
+    }
+}
+
+/// Reader of a decoded body, failing with a `413 Content Too Large` once more than `limit`
+/// bytes were read.
+pub(crate) struct LimitedBody {
+    reader: Box<dyn Read + Send>,
+    remaining: usize,
+    state: Arc<Mutex<BodyState>>,
+}
+
+impl LimitedBody {
+    pub(crate) fn new(
+        reader: Box<dyn Read + Send>,
+        limit: usize,
+        state: &Arc<Mutex<BodyState>>,
+    ) -> Self {
+        Self {
+            reader,
+            remaining: limit,
+            state: Arc::clone(state),
+        }
+    }
+}
+
+impl Read for LimitedBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let max = buf.len().min(self.remaining.saturating_add(1));
+        let n = self.reader.read(&mut buf[..max])?;
+        if n > self.remaining {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.too_large = true;
+            return Err(Error::other(HttpError::Error413));
+        }
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_drain_body_and_keep_next_request() {
+        let state = BodyState::new(BodyFraming::Length(8), b"Hello".to_vec(), false);
+        let mut socket: &[u8] = b"!!!GET / HTTP/1.1\r\n\r\n";
+        let leftover = state.lock().unwrap().drain(&mut socket, 8);
+
+        assert_eq!(leftover.as_deref(), Some(&b""[..]));
+        assert_eq!(socket, b"GET / HTTP/1.1\r\n\r\n");
+    }
+
+    #[test]
+    fn it_should_stop_draining_over_limit() {
+        let state = BodyState::new(BodyFraming::Length(10_000), Vec::new(), false);
+        let mut socket: &[u8] = &[0; 10_000];
+
+        assert!(state.lock().unwrap().drain(&mut socket, 100).is_none());
+    }
+}
@@ -1,137 +1,329 @@
-use crate::worker::MAX_BODY_SIZE;
-use std::{error::Error, iter::Peekable};
-
+//! Chunked transfer coding decoder
+//!
+//! [ChunkHandler] decodes a body sent with `Transfer-Encoding: chunked`, following the
+//! grammar of RFC 9112 section 7.1:
+//! ```text
+//! chunked-body = *chunk last-chunk trailer-section CRLF
+//! chunk        = chunk-size [ chunk-ext ] CRLF chunk-data CRLF
+//! last-chunk   = 1*("0") [ chunk-ext ] CRLF
+//! ```
+//! Chunk sizes are hexadecimal, chunk extensions are checked and ignored, and the trailer
+//! fields sent after the last chunk are kept in `trailers`.
+//!
+//! The bytes can be given in any number of calls to `parse_chunks`, split anywhere. A
+//! malformed body is a `400 Bad Request`, a chunk size that overflows or that makes the body
+//! longer than its limit a `413 Content Too Large`. A chunk-size line or a trailer section
+//! longer than the header size limit is a `431 Request Header Fields Too Large`.
+use crate::config::{DEFAULT_MAX_BODY_SIZE, DEFAULT_MAX_HEADER_SIZE};
+use crate::header_map::HeaderMap;
 use crate::http_error::HttpError;
+use std::error::Error;
 
 #[derive(PartialEq)]
 enum ChunkState {
-    Header,
-    Body,
+    // Reading the chunk-size line, with its extensions.
+    Size,
+    // Reading the chunk-data, with the number of bytes left.
+    Data(usize),
+    // Reading the CRLF after the chunk-data.
+    DataEnd,
+    // Reading the trailer section.
+    Trailer,
     Done,
-    WaitingMoreData,
 }
+
 pub struct ChunkHandler {
     pub body: Vec<u8>,
     pub leftover: Vec<u8>,
-    size_str: Vec<u8>,
-    size: usize,
+    pub trailers: HeaderMap,
+    line: Vec<u8>,
+    trailer_section: Vec<u8>,
+    body_size: usize,
+    max_body_size: Option<usize>,
+    max_header_size: usize,
     state: ChunkState,
 }
 
 impl ChunkHandler {
     pub fn new(leftover: &[u8]) -> Result<Self, Box<dyn Error>> {
         let mut chunk_handler = Self {
-            size_str: vec![0u8; 0],
-            size: 0,
-            body: vec![0u8; 0],
-            leftover: vec![0u8; 0],
-            state: ChunkState::Header,
+            body: Vec::new(),
+            leftover: Vec::new(),
+            trailers: HeaderMap::new(),
+            line: Vec::new(),
+            trailer_section: Vec::new(),
+            body_size: 0,
+            max_body_size: Some(DEFAULT_MAX_BODY_SIZE),
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            state: ChunkState::Size,
         };
-        if leftover.len() > 0 {
+        if !leftover.is_empty() {
             chunk_handler.parse_chunks(leftover)?;
         }
         Ok(chunk_handler)
     }
 
+    /// Maximum size of the decoded body, `DEFAULT_MAX_BODY_SIZE` by default. `None` disables the
+    /// limit.
+    pub fn with_max_body_size(mut self, max_body_size: Option<usize>) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Maximum size of a chunk-size line and of the trailer section, `DEFAULT_MAX_HEADER_SIZE`
+    /// by default.
+    pub fn with_max_header_size(mut self, max_header_size: usize) -> Self {
+        self.max_header_size = max_header_size;
+        self
+    }
+
     pub fn is_body_ready(&self) -> bool {
-        if self.state == ChunkState::Done {
-            return true;
-        }
-        false
+        self.state == ChunkState::Done
     }
 
+    /// Decode the given bytes. The decoded data is appended to `body` and the bytes received
+    /// after the end of the chunked body to `leftover`.
     pub fn parse_chunks(&mut self, buffer: &[u8]) -> Result<(), Box<dyn Error>> {
-        if self.body.len() >= MAX_BODY_SIZE {
-            return Err(Box::new(HttpError::Error413));
-        }
-        let mut iter = buffer.iter().peekable();
-        self.reinitialize_state();
-        loop {
+        let mut i = 0;
+        while i < buffer.len() {
             match self.state {
-                ChunkState::Header => self.parse_header(&mut iter),
-                ChunkState::Body => self.parse_body(&mut iter),
-                ChunkState::Done => return Ok(()),
-                ChunkState::WaitingMoreData => return Ok(()),
-            }?;
+                ChunkState::Size | ChunkState::Trailer => {
+                    let rest = &buffer[i..];
+                    let end = rest.iter().position(|&byte| byte == b'\n');
+                    let part = &rest[..end.unwrap_or(rest.len())];
+                    if self.line.len() + part.len() + self.trailer_section.len()
+                        > self.max_header_size
+                    {
+                        return Err(Box::new(HttpError::Error431));
+                    }
+                    self.line.extend_from_slice(part);
+                    let Some(end) = end else {
+                        return Ok(());
+                    };
+                    i += end + 1;
+                    let line = std::mem::take(&mut self.line);
+                    let line = line.strip_suffix(b"\r").ok_or(HttpError::Error400)?;
+                    if line.contains(&b'\r') {
+                        return Err(Box::new(HttpError::Error400));
+                    }
+                    self.parse_line(line)?;
+                }
+                ChunkState::Data(remaining) => {
+                    let n = remaining.min(buffer.len() - i);
+                    self.body.extend_from_slice(&buffer[i..i + n]);
+                    i += n;
+                    self.state = match remaining - n {
+                        0 => ChunkState::DataEnd,
+                        remaining => ChunkState::Data(remaining),
+                    };
+                }
+                ChunkState::DataEnd => {
+                    self.line.push(buffer[i]);
+                    i += 1;
+                    if !b"\r\n".starts_with(&self.line) {
+                        return Err(Box::new(HttpError::Error400));
+                    }
+                    if self.line.len() == 2 {
+                        self.line.clear();
+                        self.state = ChunkState::Size;
+                    }
+                }
+                ChunkState::Done => {
+                    self.leftover.extend_from_slice(&buffer[i..]);
+                    return Ok(());
+                }
+            }
         }
+        Ok(())
     }
 
-    fn reinitialize_state(&mut self) {
-        if self.state == ChunkState::WaitingMoreData {
-            if self.size == 0 {
-                self.state = ChunkState::Header;
+    fn parse_line(&mut self, line: &[u8]) -> Result<(), HttpError> {
+        if self.state == ChunkState::Trailer {
+            if line.is_empty() {
+                self.trailers = HeaderMap::parse_bytes(&self.trailer_section)?;
+                self.state = ChunkState::Done;
             } else {
-                self.state = ChunkState::Body;
+                self.trailer_section.extend_from_slice(line);
+                self.trailer_section.extend_from_slice(b"\r\n");
             }
+            return Ok(());
+        }
+        let chunk_size = parse_size_line(line)?;
+        self.body_size = self
+            .body_size
+            .checked_add(chunk_size)
+            .ok_or(HttpError::Error413)?;
+        if self.max_body_size.is_some_and(|max| self.body_size > max) {
+            return Err(HttpError::Error413);
         }
+        self.state = match chunk_size {
+            0 => ChunkState::Trailer,
+            chunk_size => ChunkState::Data(chunk_size),
+        };
+        Ok(())
     }
+}
 
-    fn parse_header<'a, I>(&mut self, iter: &mut Peekable<I>) -> Result<(), Box<dyn Error>>
-    where
-        I: Iterator<Item = &'a u8>,
-    {
-        while let Some(next) = iter.next() {
-            if let Some(peek) = iter.peek() {
-                if *next == 13 && **peek == 10 {
-                    self.size = parse_size(&self.size_str)?;
-                    self.size_str.clear();
-                    self.state = ChunkState::Body;
-                    iter.next();
-                    return Ok(());
-                } else {
-                    self.size_str.push(*next);
+// chunk-size [ chunk-ext ], chunk-size being 1*HEXDIG.
+fn parse_size_line(line: &[u8]) -> Result<usize, HttpError> {
+    let digits = line
+        .iter()
+        .position(|byte| !byte.is_ascii_hexdigit())
+        .unwrap_or(line.len());
+    if digits == 0 {
+        return Err(HttpError::Error400);
+    }
+    if !is_chunk_ext(&line[digits..]) {
+        return Err(HttpError::Error400);
+    }
+    line[..digits].iter().try_fold(0usize, |size, &digit| {
+        let digit = (digit as char).to_digit(16).unwrap_or_default() as usize;
+        size.checked_mul(16)
+            .and_then(|size| size.checked_add(digit))
+            .ok_or(HttpError::Error413)
+    })
+}
+
+// chunk-ext = *( BWS ";" BWS chunk-ext-name [ BWS "=" BWS chunk-ext-val ] )
+// chunk-ext-val = token / quoted-string
+fn is_chunk_ext(mut ext: &[u8]) -> bool {
+    loop {
+        ext = skip_whitespace(ext);
+        let Some(rest) = ext.strip_prefix(b";") else {
+            return ext.is_empty();
+        };
+        let (name, rest) = split_token(skip_whitespace(rest));
+        if name.is_empty() {
+            return false;
+        }
+        ext = skip_whitespace(rest);
+        let Some(rest) = ext.strip_prefix(b"=") else {
+            continue;
+        };
+        let rest = skip_whitespace(rest);
+        ext = match rest.strip_prefix(b"\"") {
+            Some(quoted) => match skip_quoted_string(quoted) {
+                Some(rest) => rest,
+                None => return false,
+            },
+            None => {
+                let (value, rest) = split_token(rest);
+                if value.is_empty() {
+                    return false;
                 }
-            } else {
-                self.state = ChunkState::WaitingMoreData;
-                break;
+                rest
             }
+        };
+    }
+}
+
+fn skip_whitespace(value: &[u8]) -> &[u8] {
+    let start = value
+        .iter()
+        .position(|byte| !matches!(byte, b' ' | b'\t'))
+        .unwrap_or(value.len());
+    &value[start..]
+}
+
+fn split_token(value: &[u8]) -> (&[u8], &[u8]) {
+    let end = value
+        .iter()
+        .position(|&byte| !is_tchar(byte))
+        .unwrap_or(value.len());
+    value.split_at(end)
+}
+
+fn is_tchar(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+// Skip a quoted-string whose opening quote was already consumed, and return what follows
+// the closing quote.
+fn skip_quoted_string(value: &[u8]) -> Option<&[u8]> {
+    let mut i = 0;
+    while i < value.len() {
+        match value[i] {
+            b'"' => return Some(&value[i + 1..]),
+            b'\\' if value.get(i + 1).is_some_and(|&byte| is_quoted_char(byte)) => i += 2,
+            byte if is_quoted_char(byte) && byte != b'\\' => i += 1,
+            _ => return None,
         }
-        self.state = ChunkState::WaitingMoreData;
-        Ok(())
     }
+    None
+}
 
-    fn parse_body<'a, I>(&mut self, iter: &mut Peekable<I>) -> Result<(), Box<dyn Error>>
-    where
-        I: Iterator<Item = &'a u8>,
-    {
-        if self.size == 0 {
-            expect_cr_cn(iter)?;
-            self.leftover = iter.cloned().collect::<Vec<u8>>();
-            self.state = ChunkState::Done;
-            return Ok(());
+// qdtext and quoted-pair characters: HTAB, SP, visible ASCII and obs-text.
+fn is_quoted_char(byte: u8) -> bool {
+    byte == b'\t' || byte == b' ' || (0x21..0x7F).contains(&byte) || byte >= 0x80
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BODY: &[u8] = b"a;name=value;quoted=\"a \\\"b\\\"\"\r\n0123456789\r\n\
+        5 ; flag\r\nHello\r\n000\r\nExpires: never\r\nDigest: abc\r\n\r\nNEXT";
+
+    fn decode(parts: &[&[u8]]) -> Result<ChunkHandler, Box<dyn Error>> {
+        let mut chunk_handler = ChunkHandler::new(&[])?;
+        for part in parts {
+            chunk_handler.parse_chunks(part)?;
         }
-        while self.size != 0 {
-            if let Some(next) = iter.next() {
-                self.body.push(*next);
-            } else {
-                self.state = ChunkState::WaitingMoreData;
-                break;
+        Ok(chunk_handler)
+    }
+
+    #[test]
+    fn it_should_decode_at_any_split_point() {
+        for i in 0..=BODY.len() {
+            for j in i..=BODY.len() {
+                let chunk_handler = decode(&[&BODY[..i], &BODY[i..j], &BODY[j..]]).unwrap();
+
+                assert!(chunk_handler.is_body_ready());
+                assert_eq!(chunk_handler.body, b"0123456789Hello");
+                assert_eq!(chunk_handler.trailers.get("expires"), Some("never"));
+                assert_eq!(chunk_handler.trailers.get("Digest"), Some("abc"));
+                assert_eq!(chunk_handler.leftover, b"NEXT");
             }
-            self.size -= 1;
         }
-        self.state = ChunkState::Header;
-        expect_cr_cn(iter)?;
-        Ok(())
     }
-}
 
-fn parse_size(size: &[u8]) -> Result<usize, Box<dyn Error>> {
-    if let Ok(retval) = String::from_utf8_lossy(size).parse::<usize>() {
-        Ok(retval)
-    } else {
-        Err(Box::new(HttpError::ErrorParsingChunkSize))
+    #[test]
+    fn it_should_reject_malformed_chunks() {
+        for body in [
+            &b"5\nHello\r\n0\r\n\r\n"[..],
+            b"5\r\nHelloX\r\n0\r\n\r\n",
+            b"x\r\nHello\r\n0\r\n\r\n",
+            b"5;=value\r\nHello\r\n0\r\n\r\n",
+            b"5;name=\"open\r\nHello\r\n0\r\n\r\n",
+            b"0\r\nBad Trailer\r\n\r\n",
+        ] {
+            assert!(decode(&[body]).is_err());
+        }
     }
-}
 
-fn expect_cr_cn<'a, I>(iter: &mut Peekable<I>) -> Result<(), Box<dyn Error>>
-where
-    I: Iterator<Item = &'a u8>,
-{
-    if let None = iter.next() {
-        return Err(Box::new(HttpError::Error400));
+    #[test]
+    fn it_should_answer_413_on_size_overflow() {
+        let error = decode(&[b"10000000000000000\r\n"]).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<HttpError>(),
+            Some(HttpError::Error413)
+        ));
+
+        let mut chunk_handler = ChunkHandler::new(&[]).unwrap().with_max_body_size(Some(4));
+        assert!(chunk_handler.parse_chunks(b"3\r\nabc\r\n2\r\n").is_err());
     }
-    if let None = iter.next() {
-        return Err(Box::new(HttpError::Error400));
+
+    #[test]
+    fn it_should_answer_431_on_long_trailer_section() {
+        let mut chunk_handler = ChunkHandler::new(&[]).unwrap().with_max_header_size(20);
+        chunk_handler
+            .parse_chunks(b"0\r\nExpires: never\r\n")
+            .unwrap();
+
+        let error = chunk_handler.parse_chunks(b"Digest: abc").err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<HttpError>(),
+            Some(HttpError::Error431)
+        ));
     }
-    Ok(())
 }
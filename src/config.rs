@@ -0,0 +1,98 @@
+//! Server configuration module
+//!
+//! A [ServerConfig] gathers the settings of an [HttpServer](crate::http_server::HttpServer):
+//! the thread pool, the timeouts, the keep-alive limits and the [Limits] on the size of the
+//! requests. The requests over the limits are answered with an error and the connection is
+//! closed:
+//! * `414 URI Too Long` when the request-target is longer than `max_uri_length`
+//! * `431 Request Header Fields Too Large` when the header block is larger than
+//!   `max_header_size` or has more than `max_headers` fields
+//! * `413 Content Too Large` when the body is larger than `max_body_size`. Streamed bodies
+//!   are only limited once decoded from a content coding, see [crate::body].
+//!
+//! # Example
+//! ```rust,no_run
+//! use webserv_rs::config::{Limits, ServerConfig};
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::http_server::HttpServer;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn handle_client(_: Request) -> Response {
+//!     Response::new(200, "Hello, World".as_bytes().to_vec(), vec![], ContentType::Text)
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let config = ServerConfig {
+//!         limits: Limits {
+//!             max_body_size: 1024 * 1024,
+//!             ..Limits::default()
+//!         },
+//!         max_requests: Some(100),
+//!         ..ServerConfig::default()
+//!     };
+//!     let mut server = HttpServer::with_config("127.0.0.1", 8080, config)?;
+//!     server.run(handle_client)
+//! }
+//! ```
+use crate::http_server::DEFAULT_SHUTDOWN_TIMEOUT;
+use crate::thread_pool::PoolConfig;
+use crate::worker::Timeouts;
+use std::time::Duration;
+
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 16_000;
+pub const DEFAULT_MAX_HEADERS: usize = 100;
+pub const DEFAULT_MAX_URI_LENGTH: usize = 8_000;
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024 * 10;
+
+/// Size limits of a request.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Maximum size of the request line and the header fields, in bytes.
+    pub max_header_size: usize,
+    /// Maximum number of header fields.
+    pub max_headers: usize,
+    /// Maximum length of the request-target, in bytes.
+    pub max_uri_length: usize,
+    /// Maximum size of a buffered body, in bytes.
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_headers: DEFAULT_MAX_HEADERS,
+            max_uri_length: DEFAULT_MAX_URI_LENGTH,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub pool: PoolConfig,
+    pub timeouts: Timeouts,
+    pub limits: Limits,
+    /// Maximum number of requests served on a keep-alive connection, advertised to the
+    /// client in the `Keep-Alive` header. The time a keep-alive connection waits for the
+    /// next request is the `idle` timeout.
+    pub max_requests: Option<usize>,
+    /// Let the handlers read the request bodies on demand, see [crate::body].
+    pub body_streaming: bool,
+    /// Maximum time `run` waits for in-flight requests once the shutdown is triggered.
+    pub shutdown_timeout: Duration,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            pool: PoolConfig::default(),
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            max_requests: None,
+            body_streaming: false,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Cookie module
+//!
+//! [Request::cookies](crate::request::Request::cookies) reads the `Cookie` header sent by the
+//! client. A [SetCookie] describes a cookie sent to the client with its attributes, added to
+//! a response with [Response::add_cookie](crate::response::Response::add_cookie). Every cookie
+//! is sent in its own `Set-Cookie` header, as required by RFC 6265.
+//!
+//! Cookie values can only contain a restricted set of characters: the other bytes, such as
+//! spaces, commas or semicolons, are percent-encoded. Values sent in double quotes by the
+//! client are unquoted, then percent-decoded, so that a value comes back as it was set. A
+//! value that is not valid percent-encoding is kept as is.
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::cookie::{SameSite, SetCookie};
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn login(request: Request) -> Response {
+//!     let mut response = Response::new(200, vec![], vec![], ContentType::Text);
+//!     if request.get_cookie("session").is_none() {
+//!         let cookie = SetCookie::new("session", "1234")
+//!             .with_path("/")
+//!             .with_max_age(Duration::from_secs(3600))
+//!             .with_http_only(true)
+//!             .with_same_site(SameSite::Lax);
+//!         response.add_cookie(&cookie);
+//!     }
+//!     response
+//! }
+//! ```
+use crate::http_error::HttpError;
+use crate::request::is_token;
+use crate::uri::percent_decode;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Send the cookie with cross-site requests too. Browsers require `Secure` with it.
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    expires: Option<DateTime<Utc>>,
+    max_age: Option<Duration>,
+    domain: Option<String>,
+    path: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl SetCookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Cookie asking the client to delete the cookie with the given name.
+    pub fn removal(name: &str) -> Self {
+        Self::new(name, "")
+            .with_max_age(Duration::ZERO)
+            .with_expires(DateTime::UNIX_EPOCH)
+    }
+
+    pub fn with_expires(mut self, expires: DateTime<Utc>) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Lifetime of the cookie, in seconds on the wire. It takes precedence over `Expires`.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn with_domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn with_http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Check that the name is a token and that the domain and the path cannot add
+    /// attributes or lines to the header.
+    pub fn validate(&self) -> Result<(), HttpError> {
+        let is_attribute_value = |value: &String| {
+            value
+                .bytes()
+                .all(|byte| (0x20..0x7F).contains(&byte) && byte != b';')
+        };
+        if is_token(&self.name)
+            && self.domain.iter().all(is_attribute_value)
+            && self.path.iter().all(is_attribute_value)
+        {
+            Ok(())
+        } else {
+            Err(HttpError::InvalidHeader("Set-Cookie".to_string()))
+        }
+    }
+}
+
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, encode_value(&self.value))?;
+        if let Some(expires) = self.expires {
+            write!(
+                f,
+                "; Expires={}",
+                expires.format("%a, %d %b %Y %H:%M:%S GMT")
+            )?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(domain) = self.domain.as_ref() {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(path) = self.path.as_ref() {
+            write!(f, "; Path={path}")?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={same_site}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the value of a `Cookie` header into name and value pairs, in order, the values being
+/// unquoted and percent-decoded. Pairs without a name are ignored.
+pub fn parse_cookies(header: &str) -> Vec<(String, String)> {
+    header
+        .split(';')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let name = name.trim();
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .unwrap_or(value);
+            let value = percent_decode(value, false).unwrap_or_else(|_| value.to_string());
+            (!name.is_empty()).then(|| (name.to_string(), value))
+        })
+        .collect()
+}
+
+// cookie-octet of RFC 6265: visible ASCII except DQUOTE, comma, semicolon and backslash.
+fn is_cookie_octet(byte: u8) -> bool {
+    matches!(byte, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+// Percent-encode the bytes that cannot appear in a cookie value. `%` is encoded too so that
+// the value can be decoded without ambiguity.
+fn encode_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if is_cookie_octet(byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_cookie_header() {
+        let cookies = parse_cookies("session=1234; theme=\"dark\";invalid; =empty; lang=en");
+
+        assert_eq!(
+            cookies,
+            [
+                ("session".to_string(), "1234".to_string()),
+                ("theme".to_string(), "dark".to_string()),
+                ("lang".to_string(), "en".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_decode_the_values_it_encodes() {
+        let cookie = SetCookie::new("n", "a b;c,100%").to_string();
+        let (_, value) = cookie.split_once('=').unwrap();
+
+        assert_eq!(value, "a%20b%3Bc%2C100%25");
+        assert_eq!(parse_cookies(&cookie)[0].1, "a b;c,100%");
+        assert_eq!(parse_cookies("n=50%; m=%zz")[1].1, "%zz");
+    }
+
+    #[test]
+    fn it_should_format_set_cookie() {
+        let cookie = SetCookie::new("id", "a b;c")
+            .with_domain("example.com")
+            .with_path("/")
+            .with_max_age(Duration::from_secs(60))
+            .with_secure(true)
+            .with_http_only(true)
+            .with_same_site(SameSite::Strict);
+
+        assert_eq!(
+            cookie.to_string(),
+            "id=a%20b%3Bc; Max-Age=60; Domain=example.com; Path=/; Secure; HttpOnly; SameSite=Strict"
+        );
+        assert_eq!(
+            SetCookie::removal("id").to_string(),
+            "id=; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Max-Age=0"
+        );
+    }
+
+    #[test]
+    fn it_should_reject_attribute_injection() {
+        assert!(SetCookie::new("id", "1")
+            .with_path("/; Domain=evil.com")
+            .validate()
+            .is_err());
+        assert!(SetCookie::new("bad name", "1").validate().is_err());
+    }
+}
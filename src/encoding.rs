@@ -1,30 +1,175 @@
+//! Content and transfer codings module
+//!
+//! Request bodies sent with a `Content-Encoding`, or with a `Transfer-Encoding` other than
+//! `chunked`, are decoded before reaching the handler, removing the codings in the reverse
+//! order in which they were applied. `gzip` (or `x-gzip`) and `deflate` are always
+//! supported, `br` with the `brotli` feature and `zstd` with the `zstd` feature. An unknown
+//! content coding is a `415 Unsupported Media Type`, an unknown transfer coding a
+//! `400 Bad Request`. The `Content-Encoding` header is removed once the body is decoded.
+//!
+//! A few kilobytes of compressed data can expand to gigabytes: decoding stops with a
+//! `413 Content Too Large` as soon as the decoded body is larger than `max_body_size`, see
+//! [Limits](crate::config::Limits). Streamed bodies are decoded as they are read, with the
+//! same limit on the decoded size, see [crate::body].
+//!
+//! # Example
+//! ```rust
+//! use flate2::write::GzEncoder;
+//! use flate2::Compression;
+//! use std::io::Write;
+//! use webserv_rs::encoding::{uncompress_with_limit, Encoding};
+//!
+//! let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+//! encoder.write_all(&[0; 10_000]).unwrap();
+//! let compressed = encoder.finish().unwrap();
+//!
+//! let body = uncompress_with_limit(&compressed, Encoding::Gzip, Some(10_000)).unwrap();
+//! assert_eq!(body.len(), 10_000);
+//! assert!(uncompress_with_limit(&compressed, Encoding::Gzip, Some(1_000)).is_err());
+//! ```
+use crate::config::DEFAULT_MAX_BODY_SIZE;
+use crate::http_error::HttpError;
 use flate2::read::DeflateDecoder;
 use flate2::read::GzDecoder;
 use std::error::Error;
-use std::io::Read;
+use std::io::{self, Read};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Encoding {
     Gzip,
     Deflate,
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "zstd")]
+    Zstd,
 }
 
-pub fn uncompress(data: &[u8], encoding: Encoding) -> Result<Vec<u8>, Box<dyn Error>> {
-    match encoding {
-        Encoding::Gzip => gzip(data),
-        Encoding::Deflate => deflate(data),
+impl Encoding {
+    /// Parse a coding name, ignoring its case and its parameters. `None` when the coding is
+    /// unknown or its feature is disabled.
+    pub fn parse(coding: &str) -> Option<Self> {
+        let name = coding.split(';').next().unwrap_or_default().trim();
+        match name.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            #[cfg(feature = "brotli")]
+            "br" => Some(Encoding::Brotli),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Encoding::Zstd),
+            _ => None,
+        }
     }
 }
 
-fn gzip(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
-    let mut decoder = GzDecoder::new(&data[..]);
-    let mut retval = Vec::new();
-    decoder.read_to_end(&mut retval)?;
-    Ok(retval)
+/// Decode data with at most `DEFAULT_MAX_BODY_SIZE` bytes once decoded.
+pub fn uncompress(data: &[u8], encoding: Encoding) -> Result<Vec<u8>, Box<dyn Error>> {
+    uncompress_with_limit(data, encoding, Some(DEFAULT_MAX_BODY_SIZE))
 }
 
-fn deflate(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
-    let mut decoder = DeflateDecoder::new(&data[..]);
+/// Decode data, failing with a `413 Content Too Large` as soon as the decoded data is larger
+/// than `limit`, and with a `400 Bad Request` when it is malformed. `None` disables the limit.
+pub fn uncompress_with_limit(
+    data: &[u8],
+    encoding: Encoding,
+    limit: Option<usize>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
     let mut retval = Vec::new();
-    decoder.read_to_end(&mut retval)?;
+    let max_read = limit.map_or(u64::MAX, |limit| (limit as u64).saturating_add(1));
+    if decode(data, encoding)?
+        .take(max_read)
+        .read_to_end(&mut retval)
+        .is_err()
+    {
+        return Err(Box::new(HttpError::Error400));
+    }
+    if limit.is_some_and(|limit| retval.len() > limit) {
+        return Err(Box::new(HttpError::Error413));
+    }
     Ok(retval)
 }
+
+/// Wrap a reader so that the data read from it is uncompressed on the fly.
+pub(crate) fn decoder(
+    reader: Box<dyn Read + Send>,
+    encoding: Encoding,
+) -> io::Result<Box<dyn Read + Send>> {
+    decode(reader, encoding)
+}
+
+fn decode<'a, R: Read + Send + 'a>(
+    reader: R,
+    encoding: Encoding,
+) -> io::Result<Box<dyn Read + Send + 'a>> {
+    Ok(match encoding {
+        Encoding::Gzip => Box::new(GzDecoder::new(reader)),
+        Encoding::Deflate => Box::new(DeflateDecoder::new(reader)),
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli => Box::new(brotli::Decompressor::new(reader, 4096)),
+        #[cfg(feature = "zstd")]
+        Encoding::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn it_should_stop_decoding_over_limit() {
+        let bomb = gzip(&[0; 100_000]);
+
+        let error = uncompress_with_limit(&bomb, Encoding::Gzip, Some(1_000)).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<HttpError>(),
+            Some(HttpError::Error413)
+        ));
+        assert_eq!(uncompress(&bomb, Encoding::Gzip).unwrap().len(), 100_000);
+    }
+
+    #[test]
+    fn it_should_reject_malformed_data() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"Hello").unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        assert_eq!(uncompress(&deflated, Encoding::Deflate).unwrap(), b"Hello");
+        assert!(uncompress(b"not gzip", Encoding::Gzip).is_err());
+    }
+
+    #[test]
+    fn it_should_parse_coding_names() {
+        assert_eq!(Encoding::parse("X-GZIP"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::parse("compress"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "brotli")]
+    fn it_should_decode_brotli() {
+        let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+        encoder.write_all(b"Hello").unwrap();
+
+        let encoding = Encoding::parse("br").unwrap();
+        assert_eq!(
+            uncompress(&encoder.into_inner(), encoding).unwrap(),
+            b"Hello"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn it_should_decode_zstd() {
+        let compressed = zstd::encode_all(&b"Hello"[..], 3).unwrap();
+
+        let encoding = Encoding::parse("zstd").unwrap();
+        assert_eq!(uncompress(&compressed, encoding).unwrap(), b"Hello");
+    }
+}
@@ -0,0 +1,661 @@
+//! Event loop module, enabled with the `event-loop` feature
+//!
+//! With [HttpServer::run_event_loop](crate::http_server::HttpServer::run_event_loop), a single
+//! thread waits on epoll (through `mio`) for every connection instead of giving each connection
+//! its own pool thread blocked in `read`. Bytes are fed to a [RequestParser] as they arrive;
+//! once a request is complete, it is sent to the [ThreadPool] where the handler runs, and the
+//! response comes back to the event loop to be written. A keep-alive connection waiting for its
+//! next request only costs a socket and a buffer.
+//!
+//! Timeouts, the maximum number of requests per connection and the graceful shutdown behave as
+//! with `run`, except that the write timeout applies to the whole response. When the pool queue
+//! is full, the request waits in the event loop with [OverflowPolicy::Block] and is answered
+//! `503 Service Unavailable` with [OverflowPolicy::Reject]. An upgraded connection leaves the
+//! event loop and is served by its own thread. TLS, body streaming and the interim responses
+//! sent by handlers are not supported; `Expect: 100-continue` is answered as with `run`.
+//!
+//! # Example
+//! ```rust,no_run
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::http_server::HttpServer;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn handle_client(_: Request) -> Response {
+//!     Response::new(200, "Hello, World".as_bytes().to_vec(), vec![], ContentType::Text)
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let mut server = HttpServer::new("127.0.0.1", 8080)?;
+//!     server.run_event_loop(handle_client)
+//! }
+//! ```
+use crate::config::{Limits, ServerConfig};
+use crate::handler::Handler;
+use crate::http_error::{error_response, handle_error, HttpError};
+use crate::interim::CONTINUE;
+use crate::parser::RequestParser;
+use crate::request::Request;
+use crate::response::{Response, Upgrade};
+use crate::shutdown::ShutdownHandle;
+use crate::thread_pool::{OverflowPolicy, ThreadPool};
+use crate::worker::{set_connection_headers, Timeouts};
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::error::Error;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+const FIRST_CLIENT: usize = 2;
+// Maximum time between two checks of the shutdown flag.
+const TICK: Duration = Duration::from_millis(50);
+// Time before accepting again after an error, e.g. when the process is out of file
+// descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+const EVENTS_CAPACITY: usize = 1024;
+const READ_SIZE: usize = 4096;
+
+struct Job {
+    token: Token,
+    request: Request,
+}
+
+enum State {
+    Reading,
+    // The request is handled by the pool or waits for a free slot in its queue.
+    Processing,
+    Writing { output: Vec<u8>, written: usize },
+}
+
+enum Progress {
+    Request(Box<Request>),
+    Pending,
+    Closed,
+}
+
+struct Client {
+    stream: TcpStream,
+    peer: String,
+    parser: RequestParser,
+    state: State,
+    keep_alive: bool,
+    reading_body: bool,
+    served: usize,
+    deadline: Option<Instant>,
+    // Deadline of the client in the deadlines of the event loop.
+    scheduled: Option<Instant>,
+    upgrade: Option<Upgrade>,
+    // Interim response not fully written yet, sent before the final response.
+    interim: Vec<u8>,
+}
+
+impl Client {
+    fn new(stream: TcpStream, peer: String, timeouts: &Timeouts, limits: &Limits) -> Self {
+        let mut client = Self {
+            stream,
+            peer,
+            parser: RequestParser::with_limits(limits.clone()),
+            state: State::Reading,
+            keep_alive: false,
+            reading_body: false,
+            served: 0,
+            deadline: None,
+            scheduled: None,
+            upgrade: None,
+            interim: Vec::new(),
+        };
+        client.wait_request(timeouts);
+        client
+    }
+
+    fn wait_request(&mut self, timeouts: &Timeouts) {
+        self.state = State::Reading;
+        self.reading_body = false;
+        self.deadline = match self.parser.is_empty() {
+            true => deadline(timeouts.idle),
+            false => deadline(timeouts.header),
+        };
+    }
+
+    fn is_idle(&self) -> bool {
+        matches!(self.state, State::Reading) && self.parser.is_empty()
+    }
+
+    // Read until a request is complete or until the socket has nothing more to give.
+    fn read_request(&mut self, timeouts: &Timeouts) -> Result<Progress, Box<dyn Error>> {
+        loop {
+            if let Some(request) = self.parser.parse()? {
+                return Ok(Progress::Request(Box::new(request)));
+            }
+            if self.parser.is_reading_body() && !self.reading_body {
+                self.reading_body = true;
+                self.deadline = deadline(timeouts.body);
+            }
+            if self.parser.take_continue() {
+                self.interim.extend_from_slice(CONTINUE);
+                self.write_interim()?;
+            }
+            let mut tmp = [0u8; READ_SIZE];
+            let n = match self.stream.read(&mut tmp) {
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(Progress::Pending),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Box::new(e)),
+            };
+            if n == 0 {
+                if self.reading_body {
+                    return Err(Box::new(HttpError::Error400));
+                }
+                return Ok(Progress::Closed);
+            }
+            if self.parser.is_empty() {
+                self.deadline = deadline(timeouts.header);
+            }
+            self.parser.feed(&tmp[..n]);
+        }
+    }
+
+    // Write as much of the interim response as the socket accepts.
+    fn write_interim(&mut self) -> std::io::Result<()> {
+        while !self.interim.is_empty() {
+            match self.stream.write(&self.interim) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.interim.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    // Return true once the whole response is written.
+    fn write_response(&mut self) -> std::io::Result<bool> {
+        let State::Writing { output, written } = &mut self.state else {
+            return Ok(true);
+        };
+        while *written < output.len() {
+            match self.stream.write(&output[*written..]) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(n) => *written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+}
+
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|timeout| Instant::now() + timeout)
+}
+
+pub(crate) struct EventLoop {
+    poll: Poll,
+    listener: TcpListener,
+    pool: ThreadPool<Job>,
+    responses: Receiver<(Token, Response)>,
+    clients: HashMap<Token, Client>,
+    // Deadlines of the clients, and of the listener when accepting must be retried, ordered
+    // by instant.
+    deadlines: BTreeSet<(Instant, Token)>,
+    waiting: VecDeque<Job>,
+    next_token: usize,
+    shutdown: ShutdownHandle,
+    timeouts: Timeouts,
+    limits: Limits,
+    max_requests: Option<usize>,
+}
+
+impl EventLoop {
+    pub(crate) fn new<H: Handler>(
+        listener: std::net::TcpListener,
+        handler: H,
+        config: &ServerConfig,
+        shutdown: ShutdownHandle,
+    ) -> std::io::Result<Self> {
+        let poll = Poll::new()?;
+        listener.set_nonblocking(true)?;
+        let mut listener = TcpListener::from_std(listener);
+        poll.registry()
+            .register(&mut listener, LISTENER, Interest::READABLE)?;
+        let waker = Waker::new(poll.registry(), WAKER)?;
+        let (sender, responses) = channel();
+        let pool = ThreadPool::new(&config.pool, move |job: Job| {
+            let version = job.request.version;
+            let mut response = handler.handle(job.request);
+            if let Err(e) = response.buffer_body() {
+                eprintln!("Error while reading response body: {e}");
+                response = error_response(500);
+            }
+            response.set_framing(version);
+            if sender.send((job.token, response)).is_ok() {
+                if let Err(e) = waker.wake() {
+                    eprintln!("Error while waking event loop: {e}");
+                }
+            }
+        });
+        Ok(Self {
+            poll,
+            listener,
+            pool,
+            responses,
+            clients: HashMap::new(),
+            deadlines: BTreeSet::new(),
+            waiting: VecDeque::new(),
+            next_token: FIRST_CLIENT,
+            shutdown,
+            timeouts: config.timeouts.clone(),
+            limits: config.limits.clone(),
+            max_requests: config.max_requests,
+        })
+    }
+
+    pub(crate) fn run(mut self, shutdown_timeout: Duration) -> std::io::Result<()> {
+        let mut events = Events::with_capacity(EVENTS_CAPACITY);
+        let mut shutdown_deadline = None;
+        loop {
+            if shutdown_deadline.is_none() && self.shutdown.is_shutdown() {
+                shutdown_deadline = Some(Instant::now() + shutdown_timeout);
+                self.poll.registry().deregister(&mut self.listener)?;
+            }
+            if let Some(shutdown_deadline) = shutdown_deadline {
+                self.close_idle();
+                if self.clients.is_empty() {
+                    break;
+                }
+                if Instant::now() >= shutdown_deadline {
+                    eprintln!("Shutdown timeout expired, closing remaining connections");
+                    break;
+                }
+            }
+            match self.poll.poll(&mut events, Some(self.poll_timeout())) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => result?,
+            }
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER => self.accept(),
+                    WAKER => {}
+                    token => self.advance(token),
+                }
+            }
+            while let Ok((token, response)) = self.responses.try_recv() {
+                self.respond(token, response);
+            }
+            self.dispatch_waiting();
+            self.check_deadlines();
+        }
+        self.clients.clear();
+        let remaining = shutdown_deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+            .unwrap_or_default();
+        if !self.pool.join(remaining) {
+            eprintln!("Shutdown timeout expired, some handlers are still running");
+        }
+        Ok(())
+    }
+
+    // Accept the pending connections. The listener is edge-triggered: after an error, e.g.
+    // when the process is out of file descriptors, accepting is retried after a backoff so
+    // that the connections still queued are not left waiting for the next one.
+    fn accept(&mut self) {
+        loop {
+            let (mut stream, peer) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == ErrorKind::ConnectionAborted => {
+                    eprintln!("Error while accepting connection: {e}");
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Error while accepting connection: {e}");
+                    self.deadlines
+                        .insert((Instant::now() + ACCEPT_BACKOFF, LISTENER));
+                    return;
+                }
+            };
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            let interest = Interest::READABLE | Interest::WRITABLE;
+            if let Err(e) = self.poll.registry().register(&mut stream, token, interest) {
+                eprintln!("Error while accepting connection: {e}");
+                continue;
+            }
+            let client = Client::new(stream, peer.to_string(), &self.timeouts, &self.limits);
+            self.clients.insert(token, client);
+            self.advance(token);
+        }
+    }
+
+    // Wait until the earliest deadline, and at most a tick.
+    fn poll_timeout(&self) -> Duration {
+        match self.deadlines.first() {
+            Some((deadline, _)) => deadline.saturating_duration_since(Instant::now()).min(TICK),
+            None => TICK,
+        }
+    }
+
+    // Move a connection forward, then update its place in the deadlines.
+    fn advance(&mut self, token: Token) {
+        self.advance_client(token);
+        let Some(client) = self.clients.get_mut(&token) else {
+            return;
+        };
+        if client.deadline == client.scheduled {
+            return;
+        }
+        if let Some(scheduled) = client.scheduled {
+            self.deadlines.remove(&(scheduled, token));
+        }
+        if let Some(deadline) = client.deadline {
+            self.deadlines.insert((deadline, token));
+        }
+        client.scheduled = client.deadline;
+    }
+
+    fn remove(&mut self, token: Token) -> Option<Client> {
+        let client = self.clients.remove(&token)?;
+        if let Some(scheduled) = client.scheduled {
+            self.deadlines.remove(&(scheduled, token));
+        }
+        Some(client)
+    }
+
+    // Move a connection forward as far as its socket allows.
+    fn advance_client(&mut self, token: Token) {
+        loop {
+            let Some(client) = self.clients.get_mut(&token) else {
+                return;
+            };
+            match client.state {
+                State::Processing => return,
+                State::Writing { .. } => match client.write_response() {
+                    Ok(false) => return,
+                    Ok(true) => {
+                        if let Some(upgrade) = client.upgrade.take() {
+                            self.upgrade(token, upgrade);
+                            return;
+                        }
+                        if !client.keep_alive {
+                            self.close(token);
+                            return;
+                        }
+                        client.wait_request(&self.timeouts);
+                    }
+                    Err(e) => {
+                        eprintln!("Error while writing in socket({}): {e}", client.peer);
+                        self.close(token);
+                        return;
+                    }
+                },
+                State::Reading => match client.read_request(&self.timeouts) {
+                    Ok(Progress::Request(request)) => {
+                        client.keep_alive = request.is_keep_alive();
+                        client.state = State::Processing;
+                        client.deadline = None;
+                        self.dispatch(Job {
+                            token,
+                            request: *request,
+                        });
+                    }
+                    Ok(Progress::Pending) => return,
+                    Ok(Progress::Closed) => {
+                        self.close(token);
+                        return;
+                    }
+                    Err(error) => {
+                        client.keep_alive = false;
+                        self.set_response(token, handle_error(error));
+                    }
+                },
+            }
+        }
+    }
+
+    fn dispatch(&mut self, job: Job) {
+        let job = match self.pool.try_dispatch(job) {
+            Ok(()) => return,
+            Err(job) => job,
+        };
+        match self.pool.overflow() {
+            OverflowPolicy::Block => self.waiting.push_back(job),
+            OverflowPolicy::Reject => {
+                if let Some(client) = self.clients.get_mut(&job.token) {
+                    client.keep_alive = false;
+                }
+                self.set_response(job.token, error_response(503));
+            }
+        }
+    }
+
+    fn dispatch_waiting(&mut self) {
+        while let Some(job) = self.waiting.pop_front() {
+            if !self.clients.contains_key(&job.token) {
+                continue;
+            }
+            if let Err(job) = self.pool.try_dispatch(job) {
+                self.waiting.push_front(job);
+                return;
+            }
+        }
+    }
+
+    fn respond(&mut self, token: Token, response: Response) {
+        self.set_response(token, response);
+        self.advance(token);
+    }
+
+    fn set_response(&mut self, token: Token, mut response: Response) {
+        let Some(client) = self.clients.get_mut(&token) else {
+            return;
+        };
+        client.served += 1;
+        client.upgrade = response.upgrade.take();
+        if client.upgrade.is_none() {
+            client.keep_alive = client.keep_alive
+                && !response.is_close()
+                && !self.shutdown.is_shutdown()
+                && self.max_requests.is_none_or(|max| client.served < max);
+            set_connection_headers(
+                &mut response,
+                client.keep_alive,
+                client.served,
+                &self.timeouts,
+                self.max_requests,
+            );
+        }
+        let mut output = std::mem::take(&mut client.interim);
+        if let Err(e) = response.write_to(&mut output) {
+            eprintln!("Error while writing response({}): {e}", client.peer);
+        }
+        client.state = State::Writing { output, written: 0 };
+        client.deadline = deadline(self.timeouts.write);
+    }
+
+    // Hand the connection over to the upgrade function, on its own blocking thread.
+    fn upgrade(&mut self, token: Token, upgrade: Upgrade) {
+        let Some(mut client) = self.remove(token) else {
+            return;
+        };
+        if let Err(e) = self.poll.registry().deregister(&mut client.stream) {
+            eprintln!("Error while upgrading connection({}): {e}", client.peer);
+            return;
+        }
+        let leftover = client.parser.take_buffer();
+        let mut stream: std::net::TcpStream = client.stream.into();
+        if let Err(e) = stream.set_nonblocking(false) {
+            eprintln!("Error while upgrading connection({}): {e}", client.peer);
+            return;
+        }
+        thread::spawn(move || upgrade(&mut stream, leftover));
+    }
+
+    fn check_deadlines(&mut self) {
+        let now = Instant::now();
+        while let Some(&(deadline, token)) = self.deadlines.first() {
+            if deadline > now {
+                return;
+            }
+            self.deadlines.pop_first();
+            if token == LISTENER {
+                if !self.shutdown.is_shutdown() {
+                    self.accept();
+                }
+                continue;
+            }
+            let Some(client) = self.clients.get_mut(&token) else {
+                continue;
+            };
+            client.scheduled = None;
+            if client.is_idle() || !matches!(client.state, State::Reading) {
+                self.close(token);
+                continue;
+            }
+            client.keep_alive = false;
+            self.respond(token, handle_error(Box::new(HttpError::Error408)));
+        }
+    }
+
+    fn close_idle(&mut self) {
+        let idle: Vec<Token> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.is_idle())
+            .map(|(token, _)| *token)
+            .collect();
+        for token in idle {
+            self.close(token);
+        }
+    }
+
+    fn close(&mut self, token: Token) {
+        if let Some(mut client) = self.remove(token) {
+            let _ = self.poll.registry().deregister(&mut client.stream);
+            println!("Connection end with : {}", client.peer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::content_type::ContentType;
+    use crate::http_server::HttpServer;
+    use crate::request::Request;
+    use crate::response::Response;
+    use crate::response_body::ResponseBody;
+    use crate::worker::Timeouts;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    fn handle_client_mock(request: Request) -> Response {
+        Response::new(200, request.uri.into_bytes(), vec![], ContentType::Text)
+    }
+
+    #[test]
+    fn it_should_serve_pipelined_requests() {
+        let mut server = HttpServer::new("127.0.0.1", 0).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || server.run_event_loop(handle_client_mock));
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .write_all(
+                b"GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n\
+                GET /second HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        handle.shutdown();
+        assert!(server.join().unwrap().is_ok());
+        let first = response.find("/first").unwrap();
+        let second = response.find("/second").unwrap();
+        assert!(first < second);
+        assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 2);
+    }
+
+    #[test]
+    fn it_should_buffer_streamed_body() {
+        let mut server = HttpServer::new("127.0.0.1", 0).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || {
+            server.run_event_loop(|_: Request| {
+                let chunks = vec![b"Hello".to_vec(), b" World".to_vec()];
+                Response::builder().stream(ResponseBody::chunks(chunks))
+            })
+        });
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        handle.shutdown();
+        assert!(server.join().unwrap().is_ok());
+        assert!(response.contains("Content-length: 11\r\n"));
+        assert!(response.ends_with("\r\n\r\nHello World"));
+    }
+
+    #[test]
+    fn it_should_answer_408_on_header_timeout() {
+        let mut server = HttpServer::new("127.0.0.1", 0).unwrap();
+        server.set_timeouts(Timeouts {
+            header: Some(Duration::from_millis(100)),
+            ..Timeouts::default()
+        });
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || server.run_event_loop(handle_client_mock));
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"GET / HTTP/1.1\r\nHost: ").unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        handle.shutdown();
+        assert!(server.join().unwrap().is_ok());
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[test]
+    fn it_should_close_idle_connection_after_response() {
+        let mut server = HttpServer::new("127.0.0.1", 0).unwrap();
+        server.set_timeouts(Timeouts {
+            idle: Some(Duration::from_millis(100)),
+            ..Timeouts::default()
+        });
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || server.run_event_loop(handle_client_mock));
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .write_all(b"GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let start = Instant::now();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+
+        handle.shutdown();
+        assert!(server.join().unwrap().is_ok());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(response.matches("HTTP/1.1 200 OK\r\n").count(), 1);
+    }
+}
@@ -0,0 +1,324 @@
+//! Form module
+//!
+//! [Form::parse] decodes the body of an HTML form submission according to the request
+//! `Content-Type`:
+//! * `application/x-www-form-urlencoded`: `name=value` pairs separated by `&`
+//! * `multipart/form-data`: parts separated by the boundary given in the `Content-Type`, each
+//!   part having its own headers. Parts with a `filename` are file uploads, kept as
+//!   [FilePart], the others are text fields.
+//!
+//! The number of fields and the size of each of them are limited by [FormLimits]; going over
+//! a limit is a `413 Content Too Large`. A malformed body is a `400 Bad Request` and another
+//! content type a `415 Unsupported Media Type`.
+//!
+//! # Example
+//! ```rust
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::http_error::{error_response, handle_error};
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn upload(request: Request) -> Response {
+//!     let form = match request.form() {
+//!         Ok(form) => form,
+//!         Err(e) => return handle_error(Box::new(e)),
+//!     };
+//!     let Some(file) = form.file("avatar") else {
+//!         return error_response(400);
+//!     };
+//!     let user = form.get("user").unwrap_or("anonymous");
+//!     let message = format!("{user} uploaded {} bytes", file.data.len());
+//!     Response::new(200, message.into_bytes(), vec![], ContentType::Text)
+//! }
+//! ```
+use crate::header_map::HeaderMap;
+use crate::http_error::HttpError;
+use crate::request::Request;
+use crate::uri::parse_query;
+
+pub const DEFAULT_MAX_PARTS: usize = 100;
+pub const DEFAULT_MAX_PART_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct FormLimits {
+    /// Maximum number of fields and files.
+    pub max_parts: usize,
+    /// Maximum size of a field value or of a file, in bytes.
+    pub max_part_size: usize,
+}
+
+impl Default for FormLimits {
+    fn default() -> Self {
+        Self {
+            max_parts: DEFAULT_MAX_PARTS,
+            max_part_size: DEFAULT_MAX_PART_SIZE,
+        }
+    }
+}
+
+/// A file uploaded in a `multipart/form-data` body.
+#[derive(Debug, Clone)]
+pub struct FilePart {
+    pub name: String,
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub headers: HeaderMap,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Form {
+    fields: Vec<(String, String)>,
+    files: Vec<FilePart>,
+}
+
+impl Form {
+    /// Parse the body of a request with the default limits.
+    pub fn parse(request: &Request) -> Result<Self, HttpError> {
+        Self::parse_with_limits(request, &FormLimits::default())
+    }
+
+    pub fn parse_with_limits(request: &Request, limits: &FormLimits) -> Result<Self, HttpError> {
+        let content_type = request.headers.content_type().unwrap_or_default();
+        let media_type = request.headers.media_type().unwrap_or_default();
+        if media_type.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            return Self::parse_urlencoded(&request.body, limits);
+        }
+        if media_type.eq_ignore_ascii_case("multipart/form-data") {
+            let boundary = get_parameter(content_type, "boundary").ok_or(HttpError::Error400)?;
+            return Self::parse_multipart(&request.body, &boundary, limits);
+        }
+        Err(HttpError::Error415)
+    }
+
+    pub fn parse_urlencoded(body: &[u8], limits: &FormLimits) -> Result<Self, HttpError> {
+        let body = std::str::from_utf8(body).map_err(|_| HttpError::Error400)?;
+        let pairs = body.split('&').filter(|pair| !pair.is_empty());
+        if pairs.clone().count() > limits.max_parts
+            || pairs.clone().any(|pair| pair.len() > limits.max_part_size)
+        {
+            return Err(HttpError::Error413);
+        }
+        Ok(Self {
+            fields: parse_query(body)?,
+            files: Vec::new(),
+        })
+    }
+
+    pub fn parse_multipart(
+        body: &[u8],
+        boundary: &str,
+        limits: &FormLimits,
+    ) -> Result<Self, HttpError> {
+        if boundary.is_empty() || boundary.len() > 70 {
+            return Err(HttpError::Error400);
+        }
+        let delimiter = format!("\r\n--{boundary}").into_bytes();
+        // The first delimiter is not preceded by a line break when there is no preamble.
+        let start = match body.starts_with(&delimiter[2..]) {
+            true => 0,
+            false => find(body, &delimiter).ok_or(HttpError::Error400)? + 2,
+        };
+        let mut rest = &body[start + delimiter.len() - 2..];
+        let mut form = Self::default();
+        loop {
+            if rest.starts_with(b"--") {
+                return Ok(form);
+            }
+            let line_end = find(rest, b"\r\n").ok_or(HttpError::Error400)?;
+            if rest[..line_end]
+                .iter()
+                .any(|byte| !matches!(byte, b' ' | b'\t'))
+            {
+                return Err(HttpError::Error400);
+            }
+            rest = &rest[line_end + 2..];
+            let end = find(rest, &delimiter).ok_or(HttpError::Error400)?;
+            if form.fields.len() + form.files.len() >= limits.max_parts {
+                return Err(HttpError::Error413);
+            }
+            form.add_part(&rest[..end], limits)?;
+            rest = &rest[end + delimiter.len()..];
+        }
+    }
+
+    fn add_part(&mut self, part: &[u8], limits: &FormLimits) -> Result<(), HttpError> {
+        let (head, data) = match part.starts_with(b"\r\n") {
+            true => (&part[..0], &part[2..]),
+            false => {
+                let index = find(part, b"\r\n\r\n").ok_or(HttpError::Error400)?;
+                (&part[..index], &part[index + 4..])
+            }
+        };
+        if data.len() > limits.max_part_size {
+            return Err(HttpError::Error413);
+        }
+        let head = std::str::from_utf8(head).map_err(|_| HttpError::Error400)?;
+        let headers = HeaderMap::parse(head)?;
+        let disposition = headers
+            .get("Content-Disposition")
+            .ok_or(HttpError::Error400)?;
+        let name = get_parameter(disposition, "name").ok_or(HttpError::Error400)?;
+        match get_parameter(disposition, "filename") {
+            Some(filename) => self.files.push(FilePart {
+                name,
+                filename,
+                content_type: headers.content_type().map(str::to_string),
+                headers,
+                data: data.to_vec(),
+            }),
+            None => {
+                let value = String::from_utf8(data.to_vec()).map_err(|_| HttpError::Error400)?;
+                self.fields.push((name, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// First value of a text field.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of a text field, in order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.fields
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// First file uploaded with the given field name.
+    pub fn file(&self, name: &str) -> Option<&FilePart> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    pub fn files(&self) -> &[FilePart] {
+        &self.files
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// Retrieve a parameter of a header value like `form-data; name="field"`. Quoted values
+// may contain `;` and backslash escapes.
+fn get_parameter(value: &str, name: &str) -> Option<String> {
+    let mut rest = value.split_once(';')?.1;
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        if rest.is_empty() {
+            return None;
+        }
+        let key_end = rest.find(['=', ';']).unwrap_or(rest.len());
+        let key = rest[..key_end].trim();
+        if !rest[key_end..].starts_with('=') {
+            rest = &rest[key_end..];
+            continue;
+        }
+        let after = rest[key_end + 1..].trim_start();
+        let (parameter, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => unquote(quoted)?,
+            None => {
+                let end = after.find(';').unwrap_or(after.len());
+                (after[..end].trim().to_string(), &after[end..])
+            }
+        };
+        if key.eq_ignore_ascii_case(name) {
+            return Some(parameter);
+        }
+        rest = remaining;
+    }
+}
+
+// Read a quoted string whose opening quote was already consumed, and return it with the
+// text following the closing quote.
+fn unquote(quoted: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => value.push(chars.next()?.1),
+            '"' => return Some((value, &quoted[i + 1..])),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MULTIPART: &[u8] = b"preamble\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"user\"\r\n\r\n\
+        alice\r\n--XyZ\r\n\
+        Content-Disposition: form-data; name=\"avatar\"; filename=\"me; 1.png\"\r\n\
+        Content-Type: image/png\r\n\r\n\
+        \x89PNG\r\n\r\n--XyZ--\r\n";
+
+    fn get_request(content_type: &str, body: &[u8]) -> Request {
+        let mut request = Request::parse(&format!(
+            "POST /form HTTP/1.1\r\nHost: localhost\r\nContent-Type: {content_type}"
+        ))
+        .unwrap();
+        request.body = body.to_vec();
+        request
+    }
+
+    #[test]
+    fn it_should_parse_urlencoded_form() {
+        let request = get_request(
+            "application/x-www-form-urlencoded",
+            b"name=John+Doe&tag=a&tag=b%26c",
+        );
+        let form = Form::parse(&request).unwrap();
+
+        assert_eq!(form.get("name"), Some("John Doe"));
+        assert_eq!(form.get_all("tag").collect::<Vec<_>>(), ["a", "b&c"]);
+    }
+
+    #[test]
+    fn it_should_parse_multipart_form() {
+        let request = get_request("multipart/form-data; boundary=\"XyZ\"", MULTIPART);
+        let form = Form::parse(&request).unwrap();
+
+        assert_eq!(form.get("user"), Some("alice"));
+        let file = form.file("avatar").unwrap();
+        assert_eq!(file.filename, "me; 1.png");
+        assert_eq!(file.content_type.as_deref(), Some("image/png"));
+        assert_eq!(file.data, b"\x89PNG\r\n");
+    }
+
+    #[test]
+    fn it_should_answer_413_over_limits() {
+        let request = get_request("multipart/form-data; boundary=XyZ", MULTIPART);
+        let limits = FormLimits {
+            max_parts: 1,
+            ..FormLimits::default()
+        };
+        assert!(matches!(
+            Form::parse_with_limits(&request, &limits),
+            Err(HttpError::Error413)
+        ));
+
+        let limits = FormLimits {
+            max_part_size: 4,
+            ..FormLimits::default()
+        };
+        assert!(matches!(
+            Form::parse_with_limits(&request, &limits),
+            Err(HttpError::Error413)
+        ));
+    }
+}
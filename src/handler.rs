@@ -0,0 +1,103 @@
+//! Handler module
+//!
+//! A [Handler] turns a [Request] into a [Response]. It is implemented for every
+//! `Fn(Request) -> Response + Send + Sync + 'static`, so plain functions and closures
+//! capturing their configuration can both be given to the server.
+//!
+//! Shared application state can be injected with [with_state]. The state is kept in an
+//! [Arc] and handed by reference to the handler on each request.
+//!
+//! # Example
+//! ```rust
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::handler::with_state;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! struct AppState {
+//!     hits: AtomicUsize,
+//! }
+//!
+//! let state = Arc::new(AppState { hits: AtomicUsize::new(0) });
+//! let handler = with_state(state, |state: &AppState, _: Request| {
+//!     let hits = state.hits.fetch_add(1, Ordering::Relaxed) + 1;
+//!     Response::new(200, format!("{hits}").into_bytes(), vec![], ContentType::Text)
+//! });
+//! ```
+use crate::request::Request;
+use crate::response::Response;
+use std::sync::Arc;
+
+pub trait Handler: Send + Sync + 'static {
+    fn handle(&self, request: Request) -> Response;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(Request) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, request: Request) -> Response {
+        self(request)
+    }
+}
+
+/// Handler holding a shared state passed by reference to every call.
+pub struct StateHandler<S, F> {
+    state: Arc<S>,
+    handler: F,
+}
+
+impl<S, F> Handler for StateHandler<S, F>
+where
+    S: Send + Sync + 'static,
+    F: Fn(&S, Request) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, request: Request) -> Response {
+        (self.handler)(&self.state, request)
+    }
+}
+
+pub fn with_state<S, F>(state: Arc<S>, handler: F) -> StateHandler<S, F>
+where
+    S: Send + Sync + 'static,
+    F: Fn(&S, Request) -> Response + Send + Sync + 'static,
+{
+    StateHandler { state, handler }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_type::ContentType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn get_request() -> Request {
+        Request::parse("GET / HTTP/1.1\r\nHost: localhost").unwrap()
+    }
+
+    #[test]
+    fn it_should_share_state_between_calls() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let handler = with_state(Arc::clone(&counter), |counter: &AtomicUsize, _| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Response::new(200, vec![], vec![], ContentType::Text)
+        });
+
+        handler.handle(get_request());
+        handler.handle(get_request());
+
+        assert_eq!(counter.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn it_should_accept_capturing_closures() {
+        let greeting = String::from("Hello");
+        let handler = move |_: Request| {
+            Response::new(200, greeting.as_bytes().to_vec(), vec![], ContentType::Text)
+        };
+
+        assert_eq!(handler.handle(get_request()).body, b"Hello");
+    }
+}
@@ -0,0 +1,282 @@
+//! Header map shared by [Request](crate::request::Request) and
+//! [Response](crate::response::Response)
+//!
+//! Header names are compared case-insensitively and keep the case they were inserted with.
+//! A name can appear several times: [HeaderMap::get] returns the first value and
+//! [HeaderMap::get_all] every value in order. [HeaderMap::insert] replaces the existing
+//! values while [HeaderMap::append] adds one.
+//!
+//! Names must be tokens and values must not contain control characters other than horizontal
+//! tabs, so that a header can never inject a line in a message.
+//!
+//! # Example
+//! ```rust
+//! use webserv_rs::header_map::HeaderMap;
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert("Content-Length", "42").unwrap();
+//! headers.append("Set-Cookie", "a=1").unwrap();
+//! headers.append("Set-Cookie", "b=2").unwrap();
+//!
+//! assert_eq!(headers.content_length(), Some(42));
+//! assert_eq!(headers.get_all("set-cookie").collect::<Vec<_>>(), ["a=1", "b=2"]);
+//! assert!(headers.insert("X-Bad", "a\r\nb").is_err());
+//! ```
+use crate::http_error::HttpError;
+use crate::request::is_token;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the header fields of a message head, one `name: value` per line. Obsolete line
+    /// folding, whitespace before the colon and illegal bytes are rejected.
+    pub fn parse(fields: &str) -> Result<Self, HttpError> {
+        Self::parse_bytes(fields.as_bytes())
+    }
+
+    /// Same as [HeaderMap::parse], from the raw bytes received. Invalid UTF-8 in a value is
+    /// replaced, a name must be a token.
+    pub fn parse_bytes(fields: &[u8]) -> Result<Self, HttpError> {
+        let mut headers = Self::new();
+        for line in split_lines(fields).filter(|line| !line.is_empty()) {
+            let Some(colon) = line.iter().position(|&byte| byte == b':') else {
+                return Err(HttpError::Error400);
+            };
+            let name = std::str::from_utf8(&line[..colon]).map_err(|_| HttpError::Error400)?;
+            let value = trim_whitespace(&line[colon + 1..]);
+            headers
+                .append(name, &String::from_utf8_lossy(value))
+                .map_err(|_| HttpError::Error400)?;
+        }
+        Ok(headers)
+    }
+
+    /// First value of the header.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of the header, in the order they were added.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Replace every value of the header by a single one.
+    pub fn insert(&mut self, name: &str, value: &str) -> Result<(), HttpError> {
+        validate(name, value)?;
+        self.remove(name);
+        self.entries.push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    /// Add a value to the header, keeping the existing ones.
+    pub fn append(&mut self, name: &str, value: &str) -> Result<(), HttpError> {
+        validate(name, value)?;
+        self.entries.push((name.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    /// Remove every value of the header and return the first one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(name))?;
+        let (_, value) = self.entries.remove(index);
+        self.entries
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Check if a comma separated header contains the given token, in any of its values.
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name).any(|value| has_token(value, token))
+    }
+
+    /// `Content-Length`, if present and a valid number. A value repeated in several fields
+    /// or in a comma separated list is accepted only when every copy is identical, following
+    /// RFC 9112 section 6.3.
+    pub fn content_length(&self) -> Option<usize> {
+        let mut values = self
+            .get_all("Content-Length")
+            .flat_map(|value| value.split(','))
+            .map(str::trim);
+        let length = values.next()?;
+        if length.is_empty() || !length.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        if values.any(|value| value != length) {
+            return None;
+        }
+        length.parse().ok()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.get("Content-Type")
+    }
+
+    /// `Content-Type` without its parameters, e.g. `text/html` for
+    /// `text/html; charset=utf-8`.
+    pub fn media_type(&self) -> Option<&str> {
+        self.content_type()?.split(';').next().map(str::trim)
+    }
+
+    pub fn host(&self) -> Option<&str> {
+        self.get("Host")
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+// Check if a comma separated header value contains the given token.
+pub(crate) fn has_token(value: &str, token: &str) -> bool {
+    value
+        .split(',')
+        .any(|part| part.trim().eq_ignore_ascii_case(token))
+}
+
+// Split header fields on CRLF, a bare CR or LF being left in the line.
+fn split_lines(mut fields: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        if fields.is_empty() {
+            return None;
+        }
+        match fields.windows(2).position(|pair| pair == b"\r\n") {
+            Some(index) => {
+                let line = &fields[..index];
+                fields = &fields[index + 2..];
+                Some(line)
+            }
+            None => Some(std::mem::take(&mut fields)),
+        }
+    })
+}
+
+fn trim_whitespace(value: &[u8]) -> &[u8] {
+    let is_whitespace = |byte: &u8| *byte == b' ' || *byte == b'\t';
+    let start = value
+        .iter()
+        .position(|byte| !is_whitespace(byte))
+        .unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|byte| !is_whitespace(byte))
+        .map_or(start, |end| end + 1);
+    &value[start..end]
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    is_token(name)
+}
+
+/// A value is made of visible characters, spaces, tabs and bytes above 0x7F, without
+/// leading or trailing whitespace.
+pub fn is_valid_value(value: &str) -> bool {
+    value
+        .bytes()
+        .all(|byte| byte == b'\t' || byte == b' ' || (byte >= 0x21 && byte != 0x7F))
+        && !value.starts_with([' ', '\t'])
+        && !value.ends_with([' ', '\t'])
+}
+
+fn validate(name: &str, value: &str) -> Result<(), HttpError> {
+    if is_valid_name(name) && is_valid_value(value) {
+        Ok(())
+    } else {
+        Err(HttpError::InvalidHeader(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_look_up_case_insensitively() {
+        let headers = HeaderMap::parse("content-length: 12\r\nHOST: localhost").unwrap();
+
+        assert_eq!(headers.content_length(), Some(12));
+        assert_eq!(headers.get("Host"), Some("localhost"));
+    }
+
+    #[test]
+    fn it_should_only_accept_identical_content_lengths() {
+        let content_length = |fields| HeaderMap::parse(fields).unwrap().content_length();
+
+        assert_eq!(
+            content_length("Content-Length: 5\r\nContent-Length: 5, 5"),
+            Some(5)
+        );
+        assert_eq!(
+            content_length("Content-Length: 5\r\nContent-Length: 6"),
+            None
+        );
+        assert_eq!(content_length("Content-Length: +5"), None);
+    }
+
+    #[test]
+    fn it_should_insert_append_and_remove() {
+        let mut headers = HeaderMap::new();
+        headers.append("Vary", "Accept").unwrap();
+        headers.append("vary", "Origin").unwrap();
+        assert_eq!(
+            headers.get_all("VARY").collect::<Vec<_>>(),
+            ["Accept", "Origin"]
+        );
+
+        headers.insert("Vary", "Cookie").unwrap();
+        assert_eq!(headers.get_all("Vary").collect::<Vec<_>>(), ["Cookie"]);
+
+        assert_eq!(headers.remove("vary"), Some("Cookie".to_string()));
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn it_should_reject_illegal_bytes() {
+        let mut headers = HeaderMap::new();
+
+        assert!(headers.insert("X-Name", "value\r\nInjected: 1").is_err());
+        assert!(headers.insert("X Name", "value").is_err());
+        assert!(HeaderMap::parse("Host : localhost").is_err());
+        assert!(HeaderMap::parse("Host: localhost\r\n folded").is_err());
+        assert!(headers.is_empty());
+    }
+}
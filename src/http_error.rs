@@ -12,57 +12,57 @@ pub enum HttpError {
     Error400,
     Error413,
     Error404,
+    Error408,
+    Error414,
     Error415,
-    ErrorParsingChunkSize,
+    Error417,
+    Error431,
+    Error505,
+    InvalidHeader(String),
 }
 
 impl std::error::Error for HttpError {}
 
-impl ErrorResponse for Box<HttpError> {
+impl ErrorResponse for HttpError {
     fn response_from_error(&self) -> Response {
-        match **self {
-            HttpError::Error400 => {
-                let mut file = std::fs::File::open("./html/400.html").unwrap();
-                let mut body = Vec::with_capacity(400);
-                let _ = file.read_to_end(&mut body);
-                Response::new(400, body, vec![], ContentType::TextHtml)
-            }
-            HttpError::Error404 => {
-                let mut file = std::fs::File::open("./html/404.html").unwrap();
-                let mut body = Vec::with_capacity(400);
-                let _ = file.read_to_end(&mut body);
-                Response::new(404, body, vec![], ContentType::TextHtml)
-            }
-            HttpError::Error413 => {
-                let mut file = std::fs::File::open("./html/413.html").unwrap();
-                let mut body = Vec::with_capacity(400);
-                let _ = file.read_to_end(&mut body);
-                Response::new(413, body, vec![], ContentType::TextHtml)
-            }
-            HttpError::Error415 => {
-                let mut file = std::fs::File::open("./html/415.html").unwrap();
-                let mut body = Vec::with_capacity(400);
-                let _ = file.read_to_end(&mut body);
-                Response::new(415, body, vec![], ContentType::TextHtml)
-            }
-
-            _ => {
-                let mut file = std::fs::File::open("./html/500.html").unwrap();
-                let mut body = Vec::with_capacity(400);
-                let _ = file.read_to_end(&mut body);
-                Response::new(500, body, vec![], ContentType::TextHtml)
-            }
+        match self {
+            HttpError::Error400 => error_response(400),
+            HttpError::Error404 => error_response(404),
+            HttpError::Error408 => error_response(408),
+            HttpError::Error413 => error_response(413),
+            HttpError::Error414 => error_response(414),
+            HttpError::Error415 => error_response(415),
+            HttpError::Error417 => error_response(417),
+            HttpError::Error431 => error_response(431),
+            HttpError::Error505 => error_response(505),
+            _ => error_response(500),
         }
     }
 }
 
 impl ErrorResponse for Box<dyn std::error::Error> {
+    // An `HttpError` can also come wrapped in an `io::Error`, e.g. from a body reader.
     fn response_from_error(&self) -> Response {
-        let mut file = std::fs::File::open("./html/500.html").unwrap();
-        let mut body = Vec::with_capacity(400);
+        let error = self.downcast_ref::<HttpError>().or_else(|| {
+            self.downcast_ref::<std::io::Error>()
+                .and_then(|error| error.get_ref())
+                .and_then(|error| error.downcast_ref::<HttpError>())
+        });
+        match error {
+            Some(error) => error.response_from_error(),
+            None => error_response(500),
+        }
+    }
+}
+
+/// Build the response for an error status, using the page `./html/<status>.html` as body
+/// when it exists.
+pub fn error_response(status: u32) -> Response {
+    let mut body = Vec::with_capacity(400);
+    if let Ok(mut file) = std::fs::File::open(format!("./html/{status}.html")) {
         let _ = file.read_to_end(&mut body);
-        Response::new(500, body, vec![], ContentType::TextHtml)
     }
+    Response::new(status, body, vec![], ContentType::TextHtml)
 }
 
 impl fmt::Display for HttpError {
@@ -70,10 +70,15 @@ impl fmt::Display for HttpError {
         match self {
             HttpError::Error400 => write!(f, "Error 400: Bad Request"),
             HttpError::Error404 => write!(f, "Error 404: Not Found"),
+            HttpError::Error408 => write!(f, "Error 408: Request Timeout"),
             HttpError::Error413 => write!(f, "Error 413: Content Too Large"),
+            HttpError::Error414 => write!(f, "Error 414: URI Too Long"),
             HttpError::Error415 => write!(f, "Error 415: Unsupported Media Type"),
-            HttpError::ErrorParsingChunkSize => {
-                write!(f, "Error 500: chunk header size not a valid number")
+            HttpError::Error417 => write!(f, "Error 417: Expectation Failed"),
+            HttpError::Error431 => write!(f, "Error 431: Request Header Fields Too Large"),
+            HttpError::Error505 => write!(f, "Error 505: HTTP Version Not Supported"),
+            HttpError::InvalidHeader(name) => {
+                write!(f, "Error 500: invalid header name or value for {name}")
             }
         }
     }
@@ -1,7 +1,19 @@
 //! Http Server module
 //!
-//! This server spawn thread and handle request using the users' handler. The handler
-//! provided by the user needs to take a [Request] as parameters and return a [Response].
+//! This server dispatches connections to a fixed size [ThreadPool] and handle request using
+//! the users' [Handler]. Any function or closure taking a [Request](crate::request::Request)
+//! as parameters and returning a [Response](crate::response::Response) is a handler, see
+//! [crate::handler] to share state between calls. The pool size, the queue size and what
+//! happens when the queue is full are set with a [PoolConfig], part of the [ServerConfig]
+//! which also holds the timeouts, the keep-alive limits and the size limits of the requests.
+//!
+//! `run` returns once the server is stopped through its [ShutdownHandle].
+//!
+//! With the `event-loop` feature, `run_event_loop` serves the connections from a single epoll
+//! thread and only uses the pool to run the handler, see [crate::event_loop].
+//!
+//! `set_body_streaming` lets the handlers read the request bodies from the connection on
+//! demand instead of receiving them buffered, see [crate::body].
 //!
 //! # Example
 //! ```Rust
@@ -22,35 +34,261 @@
 //!
 //!    Ok(())
 //!}
-use crate::request::Request;
-use crate::response::Response;
-use crate::worker::Worker;
-use std::net::TcpListener;
+use crate::config::{Limits, ServerConfig};
+#[cfg(feature = "event-loop")]
+use crate::event_loop::EventLoop;
+use crate::handler::Handler;
+use crate::shutdown::{Connection, ShutdownHandle};
+use crate::socket::Socket;
+use crate::thread_pool::{PoolConfig, ThreadPool};
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
+use crate::worker::{Timeouts, Worker};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
+
+// Time before accepting again after an error, e.g. when the process is out of file
+// descriptors, so that a lasting error does not spin the accept loop.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct HttpServer {
     listener: TcpListener,
+    config: ServerConfig,
+    shutdown: ShutdownHandle,
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
 }
 
 impl HttpServer {
     pub fn new(ip: &str, port: u32) -> std::io::Result<Self> {
+        Self::with_pool_config(ip, port, PoolConfig::default())
+    }
+
+    pub fn with_pool_config(ip: &str, port: u32, pool_config: PoolConfig) -> std::io::Result<Self> {
+        let config = ServerConfig {
+            pool: pool_config,
+            ..ServerConfig::default()
+        };
+        Self::with_config(ip, port, config)
+    }
+
+    pub fn with_config(ip: &str, port: u32, config: ServerConfig) -> std::io::Result<Self> {
         Ok(Self {
             listener: TcpListener::bind(format!("{}:{}", ip, port))?,
+            config,
+            shutdown: ShutdownHandle::new(),
+            #[cfg(feature = "tls")]
+            tls: None,
         })
     }
 
-    pub fn run(&mut self, handle_client: fn(Request) -> Response) -> std::io::Result<()> {
-        for stream in self.listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    thread::spawn(move || match stream.peer_addr() {
-                        Ok(peer) => Worker::new(stream, peer.to_string()).run(handle_client),
-                        Err(e) => eprintln!("Error while creating worker: {e}"),
-                    });
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Handle used to stop the server from another thread or from a signal.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Maximum time `run` waits for in-flight requests once the shutdown is triggered.
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.config.shutdown_timeout = timeout;
+    }
+
+    /// Header, body, keep-alive idle and write timeouts applied to every connection.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.config.timeouts = timeouts;
+    }
+
+    /// Maximum number of requests served on a keep-alive connection, advertised to the
+    /// client in the `Keep-Alive` header.
+    pub fn set_max_requests(&mut self, max_requests: Option<usize>) {
+        self.config.max_requests = max_requests;
+    }
+
+    /// Size limits of the requests, see [Limits].
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.config.limits = limits;
+    }
+
+    /// Call the handler as soon as the request head is received and let it read the body
+    /// with [Request::body_reader](crate::request::Request::body_reader). Ignored by the
+    /// event loop, which always buffers the bodies.
+    pub fn set_body_streaming(&mut self, body_streaming: bool) {
+        self.config.body_streaming = body_streaming;
+    }
+
+    /// Serve HTTPS instead of plain HTTP on this server.
+    #[cfg(feature = "tls")]
+    pub fn set_tls(&mut self, tls: TlsConfig) {
+        self.tls = Some(tls);
+    }
+
+    pub fn run<H: Handler>(&mut self, handler: H) -> std::io::Result<()> {
+        let context = Arc::new(ConnectionContext {
+            handler: Box::new(handler),
+            shutdown: self.shutdown.clone(),
+            config: self.config.clone(),
+            #[cfg(feature = "tls")]
+            tls: self.tls.as_ref().map(|tls| tls.server_config()),
+        });
+        let pool = ThreadPool::new(&self.config.pool, move |stream| context.serve(stream));
+        let result = self.accept_loop(&pool);
+        self.shutdown.shutdown();
+        if !pool.join(self.config.shutdown_timeout) {
+            eprintln!("Shutdown timeout expired, closing remaining connections");
+            self.shutdown.close_all();
+        }
+        result
+    }
+
+    /// Serve the connections from an event loop instead of a thread per connection. Return
+    /// an error if TLS is configured, the event loop does not support it.
+    #[cfg(feature = "event-loop")]
+    pub fn run_event_loop<H: Handler>(&mut self, handler: H) -> std::io::Result<()> {
+        #[cfg(feature = "tls")]
+        if self.tls.is_some() {
+            return Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "TLS is not supported by the event loop",
+            ));
+        }
+        let event_loop = EventLoop::new(
+            self.listener.try_clone()?,
+            handler,
+            &self.config,
+            self.shutdown.clone(),
+        )?;
+        event_loop.run(self.config.shutdown_timeout)
+    }
+
+    // Accept connections until the shutdown handle wakes the blocking `accept`.
+    fn accept_loop(&self, pool: &ThreadPool) -> std::io::Result<()> {
+        let addr = self.listener.local_addr()?;
+        self.listener.set_nonblocking(false)?;
+        self.shutdown.register_listener(addr);
+        while !self.shutdown.is_shutdown() {
+            match self.listener.accept() {
+                Ok((_, _)) if self.shutdown.is_shutdown() => break,
+                Ok((stream, _)) => pool.dispatch(stream),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => {
+                    eprintln!("Error while accepting connection: {e}");
+                    if e.kind() != ErrorKind::ConnectionAborted {
+                        thread::sleep(ACCEPT_BACKOFF);
+                    }
                 }
-                Err(e) => return Err(e),
             };
         }
+        self.shutdown.unregister_listener(addr);
         Ok(())
     }
 }
+
+// Everything a pool thread needs to serve a connection.
+struct ConnectionContext {
+    handler: Box<dyn Handler>,
+    shutdown: ShutdownHandle,
+    config: ServerConfig,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+impl ConnectionContext {
+    fn serve(&self, stream: TcpStream) {
+        let peer = match stream.peer_addr() {
+            Ok(peer) => peer.to_string(),
+            Err(e) => {
+                eprintln!("Error while creating worker: {e}");
+                return;
+            }
+        };
+        let connection = match self.shutdown.register(&stream) {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("Error while creating worker: {e}");
+                return;
+            }
+        };
+        #[cfg(feature = "tls")]
+        if let Some(config) = self.tls.as_ref() {
+            match crate::tls::accept(Arc::clone(config), stream) {
+                Ok(stream) => self.run_worker(Worker::new(stream, peer), connection),
+                Err(e) => eprintln!("Error while creating TLS session({peer}): {e}"),
+            }
+            return;
+        }
+        self.run_worker(Worker::new(stream, peer), connection);
+    }
+
+    fn run_worker<T: Socket + Send + 'static>(&self, worker: Worker<T>, connection: Connection) {
+        worker
+            .with_connection(connection)
+            .with_timeouts(self.config.timeouts.clone())
+            .with_limits(self.config.limits.clone())
+            .with_max_requests(self.config.max_requests)
+            .with_body_streaming(self.config.body_streaming)
+            .run(self.handler.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_type::ContentType;
+    use crate::request::Request;
+    use crate::response::Response;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::Instant;
+
+    fn handle_client_mock(_: Request) -> Response {
+        Response::new(200, b"Hello".to_vec(), vec![], ContentType::Text)
+    }
+
+    #[test]
+    fn it_should_close_idle_connections_on_shutdown() {
+        let mut server = HttpServer::new("127.0.0.1", 0).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || server.run(handle_client_mock));
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"Hello") {
+            let mut tmp = [0u8; 1024];
+            let n = client.read(&mut tmp).unwrap();
+            assert_ne!(n, 0);
+            response.extend_from_slice(&tmp[..n]);
+        }
+
+        handle.shutdown();
+
+        assert!(server.join().unwrap().is_ok());
+        assert_eq!(client.read(&mut [0u8; 16]).unwrap(), 0);
+    }
+
+    #[test]
+    fn it_should_wake_blocking_accept_on_shutdown() {
+        let mut server = HttpServer::new("0.0.0.0", 0).unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || server.run(handle_client_mock));
+        thread::sleep(Duration::from_millis(50));
+
+        let start = Instant::now();
+        handle.shutdown();
+
+        assert!(server.join().unwrap().is_ok());
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,125 @@
+//! Interim responses module
+//!
+//! Informational `1xx` responses can be sent on a connection before the final response of a
+//! request.
+//!
+//! The server answers `Expect: 100-continue` itself: the client waits for a `100 Continue`
+//! before sending the body, which is sent once the request head was accepted. When the head
+//! is enough to reject the request, e.g. with a `Content-Length` over the body limit, the
+//! final error response is sent instead and the connection is closed. Any other expectation
+//! is a `417 Expectation Failed`. With body streaming, the `100 Continue` is only sent when
+//! the handler starts reading the body.
+//!
+//! Handlers send other interim responses, such as `103 Early Hints`, with
+//! [Request::send_interim]. Interim responses are not sent to HTTP/1.0 clients, which do not
+//! support them, and are not supported by the event loop.
+//!
+//! # Example
+//! ```rust
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::header_map::HeaderMap;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn index(request: Request) -> Response {
+//!     let mut hints = HeaderMap::new();
+//!     hints.append("Link", "</style.css>; rel=preload; as=style").unwrap();
+//!     if let Err(e) = request.send_interim(103, &hints) {
+//!         eprintln!("Error while sending early hints: {e}");
+//!     }
+//!     Response::new(200, b"<html></html>".to_vec(), vec![], ContentType::TextHtml)
+//! }
+//! ```
+use crate::header_map::HeaderMap;
+use crate::request::{Request, Version};
+use crate::socket::Socket;
+use crate::status::StatusCode;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex, PoisonError};
+
+pub(crate) const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+/// Connection on which the interim responses of a request are written.
+pub(crate) struct Interim {
+    socket: Arc<Mutex<dyn Socket + Send>>,
+}
+
+impl Interim {
+    pub(crate) fn new(socket: Arc<Mutex<dyn Socket + Send>>) -> Self {
+        Self { socket }
+    }
+
+    fn send(&self, response: &[u8]) -> std::io::Result<()> {
+        let mut socket = self.socket.lock().unwrap_or_else(PoisonError::into_inner);
+        socket.write_all(response)?;
+        socket.flush()
+    }
+}
+
+impl fmt::Debug for Interim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Interim")
+    }
+}
+
+impl Request {
+    /// Send an informational response before the final one. The status must be a `1xx`
+    /// other than `101 Switching Protocols`, which is sent as a final response with an
+    /// upgrade. Does nothing for an HTTP/1.0 client.
+    pub fn send_interim(&self, status: u32, headers: &HeaderMap) -> std::io::Result<()> {
+        if !(100..200).contains(&status) || status == 101 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{status} is not an interim status"),
+            ));
+        }
+        if self.version == Version::Http10 {
+            return Ok(());
+        }
+        let Some(interim) = self.interim.as_ref() else {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "interim responses are not supported on this connection",
+            ));
+        };
+        let mut response = format!(
+            "HTTP/1.1 {status} {}\r\n",
+            StatusCode::new_unchecked(status).reason_phrase()
+        );
+        for (name, value) in headers {
+            response.push_str(&format!("{name}: {value}\r\n"));
+        }
+        response.push_str("\r\n");
+        interim.send(response.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::TcpStreamMock;
+
+    #[test]
+    fn it_should_send_early_hints() {
+        let socket = Arc::new(Mutex::new(TcpStreamMock::new(&[b""])));
+        let mut request = Request::parse("GET / HTTP/1.1\r\nHost: localhost").unwrap();
+        request.interim = Some(Interim::new(socket.clone()));
+        let mut headers = HeaderMap::new();
+        headers.append("Link", "</style.css>; rel=preload").unwrap();
+        request.send_interim(103, &headers).unwrap();
+
+        assert_eq!(
+            socket.lock().unwrap().receive,
+            b"HTTP/1.1 103 Early Hints\r\nLink: </style.css>; rel=preload\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn it_should_reject_final_status() {
+        let request = Request::parse("GET / HTTP/1.1\r\nHost: localhost").unwrap();
+
+        assert!(request.send_interim(200, &HeaderMap::new()).is_err());
+        assert!(request.send_interim(101, &HeaderMap::new()).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! JSON module, enabled with the `json` feature
+//!
+//! Adds [Request::json] to deserialize a JSON body and [Response::json] to serialize a value
+//! into a response, with `serde`.
+//!
+//! [Request::json] only accepts bodies sent with an `application/json` content type, or a
+//! `+json` one such as `application/problem+json`. Another content type is a
+//! `415 Unsupported Media Type` and a body that does not match the expected type a
+//! `400 Bad Request`.
+//!
+//! # Example
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use webserv_rs::http_error::handle_error;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! #[derive(Deserialize)]
+//! struct NewUser {
+//!     name: String,
+//! }
+//!
+//! #[derive(Serialize)]
+//! struct User {
+//!     id: u32,
+//!     name: String,
+//! }
+//!
+//! fn create_user(request: Request) -> Response {
+//!     match request.json::<NewUser>() {
+//!         Ok(user) => Response::json(&User { id: 1, name: user.name }),
+//!         Err(e) => handle_error(Box::new(e)),
+//!     }
+//! }
+//! ```
+use crate::content_type::ContentType;
+use crate::http_error::{error_response, HttpError};
+use crate::request::Request;
+use crate::response::Response;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+impl Request {
+    /// Deserialize the JSON body of the request.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, HttpError> {
+        let media_type = self.headers.media_type().unwrap_or_default();
+        let media_type = media_type.to_ascii_lowercase();
+        if media_type != "application/json" && !media_type.ends_with("+json") {
+            return Err(HttpError::Error415);
+        }
+        serde_json::from_slice(&self.body).map_err(|_| HttpError::Error400)
+    }
+}
+
+impl Response {
+    /// `200 OK` response with the value serialized as JSON. A value that cannot be
+    /// serialized gives a `500 Internal Server Error`.
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Response::new(200, body, vec![], ContentType::Json),
+            Err(e) => {
+                eprintln!("Error while serializing response: {e}");
+                error_response(500)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    fn get_request(content_type: &str, body: &str) -> Request {
+        let mut request = Request::parse(&format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: {content_type}"
+        ))
+        .unwrap();
+        request.body = body.as_bytes().to_vec();
+        request
+    }
+
+    #[test]
+    fn it_should_deserialize_json_body() {
+        let request = get_request("application/json; charset=utf-8", r#"{"x": 1, "y": 2}"#);
+
+        assert_eq!(request.json::<Point>().unwrap(), Point { x: 1, y: 2 });
+    }
+
+    #[test]
+    fn it_should_reject_wrong_content_type_or_body() {
+        let request = get_request("text/plain", r#"{"x": 1, "y": 2}"#);
+        assert!(matches!(request.json::<Point>(), Err(HttpError::Error415)));
+
+        let request = get_request("application/json", r#"{"x": 1}"#);
+        assert!(matches!(request.json::<Point>(), Err(HttpError::Error400)));
+    }
+
+    #[test]
+    fn it_should_serialize_json_response() {
+        let response = Response::json(&HashMap::from([("status", "ok")]));
+
+        assert_eq!(response.body, br#"{"status":"ok"}"#);
+        assert_eq!(response.get_value("Content-Type"), Some("application/json"));
+    }
+}
@@ -21,12 +21,35 @@
 //!
 //!    Ok(())
 //!}
+pub mod body;
 pub mod chunk_handler;
+pub mod config;
 pub mod content_type;
+pub mod cookie;
 pub mod encoding;
+#[cfg(feature = "event-loop")]
+pub mod event_loop;
+pub mod form;
+pub mod handler;
+pub mod header_map;
 pub mod http_error;
 pub mod http_server;
+pub mod interim;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod middleware;
 pub mod mock;
+pub mod parser;
 pub mod request;
 pub mod response;
+pub mod response_body;
+pub mod router;
+pub mod shutdown;
+pub mod socket;
+pub mod status;
+pub mod thread_pool;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod uri;
+pub mod websocket;
 pub mod worker;
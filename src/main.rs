@@ -2,64 +2,58 @@ use webserv_rs::content_type::ContentType;
 use webserv_rs::http_server::HttpServer;
 use webserv_rs::request::Request;
 use webserv_rs::response::Response;
+use webserv_rs::router::Router;
 
 const BASE_PATH: &str = "./html/dist/";
 
-fn get_index() -> (Vec<u8>, ContentType) {
-    let index = std::fs::read_to_string(format!("{}/index.html", BASE_PATH)).unwrap();
-    (index.as_bytes().to_vec(), ContentType::TextHtml)
+fn get_index(_: Request) -> Response {
+    get_file("index.html")
 }
 
-fn get_bundle() -> (Vec<u8>, ContentType) {
-    let index = std::fs::read_to_string(format!("{}/bundle.js", BASE_PATH)).unwrap();
-    (index.as_bytes().to_vec(), ContentType::JS)
+fn get_bundle(_: Request) -> Response {
+    get_file("bundle.js")
 }
 
-fn get_asset(uri: &str) -> Option<(Vec<u8>, ContentType)> {
-    if uri.contains("css") {
-        let css = std::fs::read_to_string(format!("{}/{}", BASE_PATH, uri)).unwrap();
-        Some((css.as_bytes().to_vec(), ContentType::CSS))
-    } else if uri.contains("favicon") {
-        if let Ok(favicon) = std::fs::read(format!("{}/{}", BASE_PATH, uri)) {
-            Some((favicon, ContentType::Icon))
-        } else {
-            None
-        }
-    } else if uri.contains("images") {
-        if let Some(ext) = get_image_extension(uri) {
-            if let Ok(image) = std::fs::read(format!("{}/{}", BASE_PATH, uri)) {
-                return Some((image, ContentType::Image(ext.to_string())));
-            }
-        }
-        None
-    } else {
-        None
-    }
+fn get_asset(request: Request) -> Response {
+    get_file(request.get_param("path").unwrap_or_default())
 }
 
-fn get_image_extension(uri: &str) -> Option<&str> {
-    if let Some((_, extension)) = uri.rsplit_once(uri) {
-        return Some(extension);
+fn get_file(path: &str) -> Response {
+    if path.split('/').any(|segment| segment == "..") {
+        return Response::new(400, vec![], vec![], ContentType::TextHtml);
+    }
+    let Some(content_type) = get_content_type(path) else {
+        return Response::new(404, vec![], vec![], ContentType::TextHtml);
+    };
+    match std::fs::read(format!("{}/{}", BASE_PATH, path)) {
+        Ok(body) => Response::new(200, body, vec![], content_type),
+        Err(_) => Response::new(404, vec![], vec![], ContentType::TextHtml),
     }
-    None
 }
 
-fn handle_response(request: Request) -> Response {
-    println!("uri: {}", request.uri);
-    let retval = match request.uri.as_str() {
-        "/" => Some(get_index()),
-        "/bundle.js" => Some(get_bundle()),
-        _ => get_asset(request.uri.as_str()),
-    };
-    if let Some((body, content_type)) = retval {
-        Response::new(200, body.to_vec(), vec![], content_type)
-    } else {
-        Response::new(400, vec![], vec![], ContentType::TextHtml)
+fn get_content_type(path: &str) -> Option<ContentType> {
+    let (_, extension) = path.rsplit_once('.')?;
+    match extension {
+        "html" => Some(ContentType::TextHtml),
+        "js" => Some(ContentType::JS),
+        "css" => Some(ContentType::CSS),
+        "ico" => Some(ContentType::Icon),
+        "svg" => Some(ContentType::SVG),
+        "png" | "jpeg" | "gif" | "webp" => Some(ContentType::Image(extension.to_string())),
+        "jpg" => Some(ContentType::Image("jpeg".to_string())),
+        _ => None,
     }
 }
 
 fn main() -> std::io::Result<()> {
+    let mut router = Router::new();
+    router
+        .get("/", get_index)
+        .get("/bundle.js", get_bundle)
+        .get("/*path", get_asset);
+
     let mut server = HttpServer::new("127.0.0.1", 8080)?;
-    server.run(handle_response)?;
+    server.shutdown_handle().listen_signals()?;
+    server.run(router)?;
     Ok(())
 }
@@ -0,0 +1,154 @@
+//! Middleware module
+//!
+//! A [Middleware] runs around a [Handler]. It receives the [Request] and a [Next] that calls
+//! the rest of the chain. It can modify the request before calling `next.run`, modify the
+//! returned [Response], or answer directly without calling the handler at all.
+//!
+//! Middlewares are composed with a [MiddlewareStack], which is itself a [Handler] and can be
+//! given to `HttpServer::run`. The first middleware pushed is the outermost one.
+//!
+//! # Example
+//! ```rust
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::middleware::{MiddlewareStack, Next};
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn handle_client(_: Request) -> Response {
+//!     Response::new(200, "Hello, World".as_bytes().to_vec(), vec![], ContentType::Text)
+//! }
+//!
+//! fn log(request: Request, next: Next) -> Response {
+//!     let line = format!("{} {}", request.method, request.uri);
+//!     let response = next.run(request);
+//!     println!("{line} -> {}", response.status);
+//!     response
+//! }
+//!
+//! fn server_header(request: Request, next: Next) -> Response {
+//!     let mut response = next.run(request);
+//!     response.set_header("X-Powered-By", "webserv-rs");
+//!     response
+//! }
+//!
+//! let mut stack = MiddlewareStack::new(handle_client);
+//! stack.push(log).push(server_header);
+//! ```
+use crate::handler::Handler;
+use crate::request::Request;
+use crate::response::Response;
+
+pub trait Middleware: Send + Sync + 'static {
+    fn call(&self, request: Request, next: Next) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(Request, Next) -> Response + Send + Sync + 'static,
+{
+    fn call(&self, request: Request, next: Next) -> Response {
+        self(request, next)
+    }
+}
+
+/// Remaining part of the chain: the next middlewares and the handler.
+pub struct Next<'a> {
+    middlewares: &'a [Box<dyn Middleware>],
+    handler: &'a dyn Handler,
+}
+
+impl Next<'_> {
+    pub fn run(self, request: Request) -> Response {
+        match self.middlewares.split_first() {
+            Some((middleware, middlewares)) => middleware.call(
+                request,
+                Next {
+                    middlewares,
+                    handler: self.handler,
+                },
+            ),
+            None => self.handler.handle(request),
+        }
+    }
+}
+
+pub struct MiddlewareStack<H: Handler> {
+    middlewares: Vec<Box<dyn Middleware>>,
+    handler: H,
+}
+
+impl<H: Handler> MiddlewareStack<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            middlewares: Vec::new(),
+            handler,
+        }
+    }
+
+    /// Add a middleware inside the ones already pushed.
+    pub fn push<M: Middleware>(&mut self, middleware: M) -> &mut Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+}
+
+impl<H: Handler> Handler for MiddlewareStack<H> {
+    fn handle(&self, request: Request) -> Response {
+        Next {
+            middlewares: &self.middlewares,
+            handler: &self.handler,
+        }
+        .run(request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_type::ContentType;
+
+    fn echo_uri(request: Request) -> Response {
+        Response::new(200, request.uri.into_bytes(), vec![], ContentType::Text)
+    }
+
+    fn get_request(uri: &str) -> Request {
+        Request::parse(&format!("GET {uri} HTTP/1.1\r\nHost: localhost")).unwrap()
+    }
+
+    #[test]
+    fn it_should_run_middlewares_in_order() {
+        let mut stack = MiddlewareStack::new(echo_uri);
+        stack
+            .push(|mut request: Request, next: Next| {
+                request.uri.push_str("/outer");
+                let mut response = next.run(request);
+                response.body.extend_from_slice(b"|outer");
+                response
+            })
+            .push(|mut request: Request, next: Next| {
+                request.uri.push_str("/inner");
+                let mut response = next.run(request);
+                response.body.extend_from_slice(b"|inner");
+                response
+            });
+
+        let response = stack.handle(get_request("/root"));
+
+        assert_eq!(response.body, b"/root/outer/inner|inner|outer");
+    }
+
+    #[test]
+    fn it_should_short_circuit() {
+        let mut stack = MiddlewareStack::new(echo_uri);
+        stack.push(|request: Request, next: Next| {
+            if request.get_value("Authorization").is_none() {
+                return Response::new(401, vec![], vec![], ContentType::Text);
+            }
+            next.run(request)
+        });
+
+        let response = stack.handle(get_request("/private"));
+
+        assert_eq!(response.status, 401);
+    }
+}
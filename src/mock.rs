@@ -1,4 +1,6 @@
+use crate::socket::Socket;
 use std::io::{Read, Write};
+use std::time::Duration;
 
 pub const REGULAR_PACKET: &[&[u8]] = &[&[
     80, 79, 83, 84, 32, 47, 32, 72, 84, 84, 80, 47, 49, 46, 49, 13, 10, 72, 111, 115, 116, 58, 32,
@@ -46,7 +48,7 @@ pub struct TcpStreamMock {
 impl TcpStreamMock {
     pub fn new(request_bytes: &[&[u8]]) -> Self {
         let mut data = Vec::new();
-        for entry in request_bytes.into_iter() {
+        for entry in request_bytes.iter() {
             data.push(entry.to_vec())
         }
         data.reverse();
@@ -96,3 +98,13 @@ impl Write for TcpStreamMock {
         Ok(())
     }
 }
+
+impl Socket for TcpStreamMock {
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+        Ok(())
+    }
+}
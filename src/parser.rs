@@ -0,0 +1,406 @@
+//! Request parser
+//!
+//! [RequestParser] turns the bytes received on a connection into [Request]s. It does not
+//! read from the connection itself: bytes are given with `feed` and `parse` returns a request
+//! once its header block and its body are complete. Bytes received after a request are kept
+//! for the next one.
+//!
+//! The framing of the body follows RFC 9112 section 6 strictly, so that no request can be
+//! read differently by a proxy in front of the server: a request with both
+//! `Transfer-Encoding` and `Content-Length`, with conflicting `Content-Length` values, with a
+//! `chunked` coding that is not the final one, or with a line ending in a bare LF or CR, is a
+//! `400 Bad Request` and the connection is closed. Empty lines received before a request line
+//! are ignored, as RFC 9112 section 2.2 asks.
+//!
+//! A request head with an expectation other than `100-continue` is a
+//! `417 Expectation Failed`. When the client waits for a `100 Continue` before sending the
+//! body, `take_continue` tells it to the caller, which sends the interim response.
+//!
+//! With body streaming, `parse_head` returns the request as soon as its header block is
+//! complete, with the framing of its body, and the body is left to a
+//! [Body](crate::body::Body) reader.
+//!
+//! The header block is scanned incrementally: each call to `parse` only looks at the bytes
+//! received since the previous one, and the head is parsed in place from the buffer, which
+//! is kept for the whole connection. The only allocations are the ones of the [Request].
+//!
+//! This lets the blocking [Worker](crate::worker::Worker) and the event loop share the same
+//! parsing logic, whatever the way bytes are received.
+use crate::chunk_handler::ChunkHandler;
+use crate::config::Limits;
+use crate::encoding::{uncompress_with_limit, Encoding};
+use crate::header_map::HeaderMap;
+use crate::http_error::HttpError;
+use crate::request::{Request, Version};
+use std::error::Error;
+
+pub(crate) enum BodyFraming {
+    Length(usize),
+    Chunked(ChunkHandler),
+}
+
+/// Request head with the framing of its body, `None` when it has no body.
+pub(crate) type Head = (Request, Option<BodyFraming>);
+
+enum ParserState {
+    Head,
+    Body(Box<Request>, BodyFraming),
+}
+
+// Progress of the scan of a request head, so that the bytes already scanned are not scanned
+// again when more are received.
+#[derive(Default)]
+struct HeadScan {
+    // Next byte to scan.
+    position: usize,
+    // Start of the line being scanned.
+    line_start: usize,
+    // Number of header fields scanned.
+    fields: usize,
+    // Positions of the spaces around the request-target.
+    target_start: Option<usize>,
+    target_end: Option<usize>,
+}
+
+impl HeadScan {
+    // Scan the bytes received since the last call and return the size of the head, empty
+    // line included, once it is complete.
+    fn scan(&mut self, buffer: &[u8], limits: &Limits) -> Result<Option<usize>, HttpError> {
+        let mut head_size = None;
+        while self.position < buffer.len() {
+            let in_request_line = self.line_start == 0;
+            let Some(offset) = buffer[self.position..].iter().position(|&byte| {
+                byte == b'\r' || byte == b'\n' || (in_request_line && byte == b' ')
+            }) else {
+                self.position = buffer.len();
+                break;
+            };
+            let i = self.position + offset;
+            self.position = i + 1;
+            match buffer[i] {
+                b' ' if self.target_start.is_none() => self.target_start = Some(i + 1),
+                b' ' => self.target_end = self.target_end.or(Some(i)),
+                b'\r' => match buffer.get(i + 1) {
+                    None => {
+                        // The LF may still come, the CR is scanned again with it.
+                        self.position = i;
+                        break;
+                    }
+                    Some(b'\n') => {
+                        self.position = i + 2;
+                        if i == self.line_start {
+                            head_size = Some(i + 2);
+                            break;
+                        }
+                        if in_request_line {
+                            self.check_target(i, limits)?;
+                        } else {
+                            self.fields += 1;
+                        }
+                        self.line_start = i + 2;
+                    }
+                    Some(_) => return Err(HttpError::Error400),
+                },
+                _ => return Err(HttpError::Error400),
+            }
+        }
+        if self.line_start == 0 {
+            self.check_target(self.position, limits)?;
+        }
+        let size = head_size.map_or(buffer.len(), |size| size - 4);
+        if size > limits.max_header_size || self.fields > limits.max_headers {
+            return Err(HttpError::Error431);
+        }
+        Ok(head_size)
+    }
+
+    // Check the length of the request-target, even before the whole request line is received.
+    fn check_target(&self, line_end: usize, limits: &Limits) -> Result<(), HttpError> {
+        let Some(start) = self.target_start else {
+            return Ok(());
+        };
+        let end = self.target_end.unwrap_or(line_end);
+        if end.saturating_sub(start) > limits.max_uri_length {
+            return Err(HttpError::Error414);
+        }
+        Ok(())
+    }
+}
+
+pub struct RequestParser {
+    buffer: Vec<u8>,
+    scan: HeadScan,
+    state: ParserState,
+    expect_continue: bool,
+    limits: Limits,
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestParser {
+    pub fn new() -> Self {
+        Self::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> Self {
+        Self {
+            buffer: Vec::new(),
+            scan: HeadScan::default(),
+            state: ParserState::Head,
+            expect_continue: false,
+            limits,
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// True when no byte of the next request was received.
+    pub fn is_empty(&self) -> bool {
+        matches!(self.state, ParserState::Head) && self.buffer.is_empty()
+    }
+
+    /// True when the header block was parsed and the parser waits for the body.
+    pub fn is_reading_body(&self) -> bool {
+        matches!(self.state, ParserState::Body(..))
+    }
+
+    /// Give back the bytes received but not parsed yet.
+    pub fn take_buffer(&mut self) -> Vec<u8> {
+        self.scan = HeadScan::default();
+        std::mem::take(&mut self.buffer)
+    }
+
+    /// Return the next request if every byte of it was received.
+    pub fn parse(&mut self) -> Result<Option<Request>, Box<dyn Error>> {
+        if let ParserState::Head = self.state {
+            match self.parse_head(Some(self.limits.max_body_size))? {
+                Some((request, Some(framing))) => {
+                    self.expect_continue = request.expects_continue();
+                    self.state = ParserState::Body(Box::new(request), framing);
+                }
+                Some((request, None)) => return Ok(Some(request)),
+                None => return Ok(None),
+            }
+        }
+        let request = self.parse_body()?;
+        if request.is_some() {
+            self.expect_continue = false;
+        }
+        Ok(request)
+    }
+
+    /// True, once, when the client waits for a `100 Continue` before sending the body of
+    /// the request being parsed.
+    pub fn take_continue(&mut self) -> bool {
+        std::mem::take(&mut self.expect_continue)
+    }
+
+    /// Return the next request head if it was received, with the framing of its body. The
+    /// body is left in the buffer. A `Content-Length` over `max_body_size` is a
+    /// `413 Content Too Large`.
+    pub(crate) fn parse_head(
+        &mut self,
+        max_body_size: Option<usize>,
+    ) -> Result<Option<Head>, Box<dyn Error>> {
+        if self.scan.position == 0 {
+            let empty_lines = self
+                .buffer
+                .chunks(2)
+                .take_while(|line| *line == b"\r\n")
+                .count();
+            self.buffer.drain(..empty_lines * 2);
+        }
+        let Some(head_size) = self.scan.scan(&self.buffer, &self.limits)? else {
+            return Ok(None);
+        };
+        let request = Request::parse_bytes(&self.buffer[..head_size - 4])?;
+        self.buffer.drain(..head_size);
+        self.scan = HeadScan::default();
+        let framing = match request.is_body() {
+            true => Some(get_framing(&request, max_body_size, &self.limits)?),
+            false => None,
+        };
+        if framing.is_some() {
+            get_body_codings(&request)?;
+        }
+        if !is_expectation_met(&request) {
+            return Err(Box::new(HttpError::Error417));
+        }
+        Ok(Some((request, framing)))
+    }
+
+    fn parse_body(&mut self) -> Result<Option<Request>, Box<dyn Error>> {
+        let ParserState::Body(_, framing) = &mut self.state else {
+            return Ok(None);
+        };
+        let mut trailers = HeaderMap::new();
+        let body = match framing {
+            BodyFraming::Length(length) => {
+                if self.buffer.len() < *length {
+                    return Ok(None);
+                }
+                let body = self.buffer[..*length].to_vec();
+                self.buffer.drain(..*length);
+                body
+            }
+            BodyFraming::Chunked(chunk_handler) => {
+                chunk_handler.parse_chunks(&self.buffer)?;
+                self.buffer.clear();
+                if !chunk_handler.is_body_ready() {
+                    return Ok(None);
+                }
+                self.buffer.append(&mut chunk_handler.leftover);
+                trailers = std::mem::take(&mut chunk_handler.trailers);
+                std::mem::take(&mut chunk_handler.body)
+            }
+        };
+        let ParserState::Body(mut request, _) =
+            std::mem::replace(&mut self.state, ParserState::Head)
+        else {
+            return Ok(None);
+        };
+        decode_body(&mut request, body, self.limits.max_body_size)?;
+        request.trailers = trailers;
+        Ok(Some(*request))
+    }
+}
+
+// `100-continue` is the only expectation defined by RFC 9110.
+fn is_expectation_met(request: &Request) -> bool {
+    request
+        .headers
+        .get_all("Expect")
+        .flat_map(|value| value.split(','))
+        .all(|expectation| expectation.trim().eq_ignore_ascii_case("100-continue"))
+}
+
+fn get_framing(
+    request: &Request,
+    max_body_size: Option<usize>,
+    limits: &Limits,
+) -> Result<BodyFraming, Box<dyn Error>> {
+    if request.headers.contains("Transfer-Encoding") {
+        let chunked = request
+            .transfer_codings()
+            .filter(|coding| coding.eq_ignore_ascii_case("chunked"))
+            .count();
+        if request.headers.contains("Content-Length")
+            || request.version == Version::Http10
+            || chunked != 1
+            || !request.is_chunked()
+        {
+            return Err(Box::new(HttpError::Error400));
+        }
+        let chunk_handler = ChunkHandler::new(&[])?
+            .with_max_body_size(max_body_size)
+            .with_max_header_size(limits.max_header_size);
+        Ok(BodyFraming::Chunked(chunk_handler))
+    } else if let Some(body_length) = request.get_content_length() {
+        if max_body_size.is_some_and(|max| body_length > max) {
+            return Err(Box::new(HttpError::Error413));
+        }
+        Ok(BodyFraming::Length(body_length))
+    } else {
+        Err(Box::new(HttpError::Error400))
+    }
+}
+
+// Remove the codings of the body, stopping as soon as it is larger than the limit.
+fn decode_body(request: &mut Request, body: Vec<u8>, limit: usize) -> Result<(), Box<dyn Error>> {
+    let mut body = body;
+    for encoding in get_body_codings(request)? {
+        body = uncompress_with_limit(&body, encoding, Some(limit))?;
+    }
+    request.body = body;
+    request.headers.remove("Content-Encoding");
+    Ok(())
+}
+
+/// Transfer codings other than `chunked` then content codings applied to the body, in the
+/// order in which they must be decoded. An unknown transfer coding is a `400 Bad Request`, an
+/// unknown content coding a `415 Unsupported Media Type`.
+pub(crate) fn get_body_codings(request: &Request) -> Result<Vec<Encoding>, HttpError> {
+    let transfer_codings: Vec<&str> = request.transfer_codings().collect();
+    let content_codings: Vec<&str> = request
+        .headers
+        .get_all("Content-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"))
+        .collect();
+    let transfer_codings = transfer_codings
+        .into_iter()
+        .rev()
+        .filter(|coding| !coding.eq_ignore_ascii_case("chunked"))
+        .map(|coding| Encoding::parse(coding).ok_or(HttpError::Error400));
+    let content_codings = content_codings
+        .into_iter()
+        .rev()
+        .map(|coding| Encoding::parse(coding).ok_or(HttpError::Error415));
+    transfer_codings.chain(content_codings).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const REQUESTS: &[u8] =
+        b"POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nHello\
+        GET /next HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    #[test]
+    fn it_should_resume_parsing_byte_by_byte() {
+        let mut parser = RequestParser::new();
+        let mut requests = Vec::new();
+        for byte in REQUESTS {
+            parser.feed(&[*byte]);
+            while let Some(request) = parser.parse().unwrap() {
+                requests.push(request);
+            }
+        }
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].body, b"Hello");
+        assert_eq!(requests[0].headers.content_length(), Some(5));
+        assert_eq!(requests[1].uri, "/next");
+        assert!(parser.is_empty());
+    }
+
+    #[test]
+    fn it_should_ignore_empty_lines_before_request_line() {
+        let mut parser = RequestParser::new();
+        parser.feed(b"\r\n\r");
+        assert!(parser.parse().unwrap().is_none());
+
+        parser.feed(b"\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\n\r\n");
+        assert_eq!(parser.parse().unwrap().unwrap().uri, "/");
+        assert!(parser.parse().unwrap().is_none());
+
+        parser.feed(b"\nGET / HTTP/1.1\r\n\r\n");
+        assert!(parser.parse().is_err());
+    }
+
+    #[test]
+    fn it_should_reject_a_bare_line_ending_split_across_reads() {
+        let mut parser = RequestParser::new();
+        parser.feed(b"GET / HTTP/1.1\r");
+        assert!(parser.parse().unwrap().is_none());
+
+        parser.feed(b"Host: localhost\r\n\r\n");
+        let error = parser.parse().err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<HttpError>(),
+            Some(HttpError::Error400)
+        ));
+    }
+}
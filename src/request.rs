@@ -7,74 +7,308 @@
 //! * body
 //! * headers
 //!
-//! Headers are a Vec<(String, String)> struct.
+//! Headers are stored in a [HeaderMap], looked up case-insensitively.
+//!
+//! [Request::parse] validates the request line following RFC 9112: the method must be a
+//! token, see [Method], and the version must be `HTTP/1.0` or `HTTP/1.1`, see [Version].
+//! A malformed request line is a `400 Bad Request`, a well-formed but unknown version a
+//! `505 HTTP Version Not Supported`.
+//!
+//! The request-target is kept raw in `uri` and parsed in `target`, see [Uri] for the
+//! decoded path and the query parameters.
+//!
+//! The trailer fields sent after a chunked body are kept apart from the headers, in
+//! `trailers`.
+//!
+//! When body streaming is enabled on the server, the body is not read before the handler is
+//! called: it is read on demand from [Request::body_reader], or all at once into `body` with
+//! [Request::read_body]. See [Body].
+//!
+//! When the request is dispatched by a [Router](crate::router::Router), the parameters
+//! captured in the route pattern are available in `params`.
+use crate::body::Body;
+use crate::cookie::parse_cookies;
+use crate::form::Form;
+use crate::header_map::HeaderMap;
+use crate::http_error::HttpError;
+use crate::interim::Interim;
+use crate::uri::Uri;
 use std::fmt;
+use std::io::{ErrorKind, Read};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any other method token, e.g. `PROPFIND`.
+    Extension(String),
+}
+
+impl Method {
+    /// Parse a method token. Methods are case-sensitive: `get` is an extension method.
+    pub fn parse(method: &str) -> Result<Self, HttpError> {
+        let method = match method {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            _ if is_token(method) => Method::Extension(method.to_string()),
+            _ => return Err(HttpError::Error400),
+        };
+        Ok(method)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Extension(method) => method,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl Version {
+    /// Parse an HTTP version. A version with the right syntax other than 1.0 and 1.1 is a
+    /// `505 HTTP Version Not Supported`, anything else a `400 Bad Request`.
+    pub fn parse(version: &str) -> Result<Self, HttpError> {
+        match version {
+            "HTTP/1.0" => Ok(Version::Http10),
+            "HTTP/1.1" => Ok(Version::Http11),
+            _ => match version.strip_prefix("HTTP/").map(str::as_bytes) {
+                Some([major, b'.', minor]) if major.is_ascii_digit() && minor.is_ascii_digit() => {
+                    Err(HttpError::Error505)
+                }
+                _ => Err(HttpError::Error400),
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct Request {
     pub uri: String,
+    pub target: Uri,
     pub body: Vec<u8>,
-    pub method: String,
-    pub version: String,
-    pub headers: Vec<(String, String)>,
+    pub method: Method,
+    pub version: Version,
+    pub headers: HeaderMap,
+    /// Trailer fields sent after a chunked body.
+    pub trailers: HeaderMap,
+    pub params: Vec<(String, String)>,
+    pub(crate) stream: Option<Body>,
+    pub(crate) interim: Option<Interim>,
 }
 
 impl Request {
-    pub fn new(response: &str) -> Self {
-        Self {
-            method: get_method(&response),
-            uri: get_uri(&response),
-            version: get_version(&response),
-            headers: get_headers(&response),
+    /// Parse the request line and the header fields of a request head, without the empty
+    /// line ending it.
+    pub fn parse(head: &str) -> Result<Self, HttpError> {
+        Self::parse_bytes(head.as_bytes())
+    }
+
+    /// Same as [Request::parse], from the raw bytes received. The request line must be ASCII,
+    /// invalid UTF-8 in header values is replaced.
+    pub fn parse_bytes(head: &[u8]) -> Result<Self, HttpError> {
+        let (request_line, fields) = match head.windows(2).position(|pair| pair == b"\r\n") {
+            Some(index) => (&head[..index], &head[index + 2..]),
+            None => (head, &[][..]),
+        };
+        let request_line = std::str::from_utf8(request_line).map_err(|_| HttpError::Error400)?;
+        let (method, uri, version) = parse_request_line(request_line)?;
+        Ok(Self {
+            method,
+            target: Uri::parse(&uri)?,
+            uri,
+            version,
+            headers: HeaderMap::parse_bytes(fields)?,
+            trailers: HeaderMap::new(),
             body: Vec::new(),
-        }
+            params: Vec::new(),
+            stream: None,
+            interim: None,
+        })
     }
 
-    // Retrieve the value of the given header.
+    // Retrieve the first value of the given header, ignoring the case of its name.
     pub fn get_value(&self, key: &str) -> Option<&str> {
-        for (header_key, value) in self.headers.iter() {
-            if header_key == key {
-                return Some(value);
-            }
+        self.headers.get(key)
+    }
+
+    /// Decoded and normalized path of the request-target.
+    pub fn path(&self) -> &str {
+        self.target.path()
+    }
+
+    /// First value of a query parameter.
+    pub fn get_query(&self, key: &str) -> Option<&str> {
+        self.target.get_query(key)
+    }
+
+    /// Host the request is for: the authority of an absolute-form target, which takes
+    /// precedence following RFC 9112 section 3.2.2, or the `Host` header.
+    pub fn host(&self) -> Option<&str> {
+        self.target.authority().or(self.headers.host())
+    }
+
+    /// Cookies sent in the `Cookie` headers, in order.
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        self.headers
+            .get_all("Cookie")
+            .flat_map(parse_cookies)
+            .collect()
+    }
+
+    /// Value of the first cookie with the given name.
+    pub fn get_cookie(&self, name: &str) -> Option<String> {
+        self.cookies()
+            .into_iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Decode the body of a form submission, see [Form].
+    pub fn form(&self) -> Result<Form, HttpError> {
+        Form::parse(self)
+    }
+
+    /// Reader of the body. With body streaming, the body is read from the connection as
+    /// the reader is used; otherwise the reader returns the buffered `body`, which is left
+    /// empty.
+    pub fn body_reader(&mut self) -> Body {
+        match self.stream.take() {
+            Some(stream) => stream,
+            None => Body::buffered(std::mem::take(&mut self.body)),
         }
-        None
     }
 
-    // Check if HTTP packed is chunked
-    pub fn is_chunked(&self) -> bool {
-        if let Some(te) = self.get_value("Transfer-Encoding") {
-            if te.to_lowercase().trim() == "chunked" {
-                return true;
+    /// Read a streamed body into `body`. A body longer than `limit` is a
+    /// `413 Content Too Large`, a body that cannot be read or decoded a `400 Bad Request`
+    /// and a body not received in time a `408 Request Timeout`. Does nothing when the body
+    /// is already buffered.
+    pub fn read_body(&mut self, limit: usize) -> Result<(), HttpError> {
+        let Some(mut stream) = self.stream.take() else {
+            return Ok(());
+        };
+        let mut body = Vec::new();
+        match (&mut stream)
+            .take((limit as u64).saturating_add(1))
+            .read_to_end(&mut body)
+        {
+            Ok(_) if body.len() > limit => Err(HttpError::Error413),
+            Ok(_) => {
+                self.body = body;
+                self.trailers = stream.trailers();
+                Ok(())
             }
-            if let Some((_, last)) = te.rsplit_once(",") {
-                if last.trim().to_lowercase() == "chunked" {
-                    return true;
-                }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Err(HttpError::Error408)
             }
+            Err(_) => Err(HttpError::Error400),
         }
-        false
+    }
+
+    // Retrieve the value of a parameter captured by the router.
+    pub fn get_param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param_key, _)| param_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Check if the body is sent with the chunked transfer coding, which must be the last
+    /// one applied.
+    pub fn is_chunked(&self) -> bool {
+        self.transfer_codings()
+            .last()
+            .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"))
+    }
+
+    /// Transfer codings listed in every `Transfer-Encoding` field, in the order in which
+    /// they were applied.
+    pub(crate) fn transfer_codings(&self) -> impl Iterator<Item = &str> {
+        self.headers
+            .get_all("Transfer-Encoding")
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|coding| !coding.is_empty())
+    }
+
+    // Check if the client wants the connection to persist after this request, following
+    // RFC 9112 section 9.3: HTTP/1.1 is persistent unless `Connection: close` is sent,
+    // HTTP/1.0 is persistent only with `Connection: keep-alive`.
+    pub fn is_keep_alive(&self) -> bool {
+        if self.headers.has_token("Connection", "close") {
+            return false;
+        }
+        match self.version {
+            Version::Http11 => true,
+            Version::Http10 => self.headers.has_token("Connection", "keep-alive"),
+        }
+    }
+
+    /// Check if an HTTP/1.1 client waits for a `100 Continue` before sending the body.
+    /// HTTP/1.0 clients do not know interim responses, their expectation is ignored.
+    pub fn expects_continue(&self) -> bool {
+        self.version == Version::Http11 && self.headers.has_token("Expect", "100-continue")
     }
 
     pub fn is_body(&self) -> bool {
-        if let Some(_) = self.get_value("Content-Length") {
+        if self.get_value("Content-Length").is_some() {
             return true;
         }
-        if let Some(_) = self.get_value("Transfer-Encoding") {
+        if self.get_value("Transfer-Encoding").is_some() {
             return true;
         }
         false
     }
 
     pub fn get_content_length(&self) -> Option<usize> {
-        if let Some(length) = self.get_value("Content-Length") {
-            if let Ok(length) = length.parse::<usize>() {
-                return Some(length);
-            } else {
-                return None;
-            }
-        }
-        None
+        self.headers.content_length()
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -82,36 +316,31 @@ impl Request {
     }
 }
 
-fn get_method(response: &str) -> String {
-    let mut splits = response.split_ascii_whitespace();
-    splits.next().unwrap().to_string()
-}
-
-fn get_uri(response: &str) -> String {
-    let mut splits = response.split_ascii_whitespace();
-    splits.next().unwrap();
-    splits.next().unwrap().trim().to_string()
+// request-line = method SP request-target SP HTTP-version
+fn parse_request_line(line: &str) -> Result<(Method, String, Version), HttpError> {
+    let mut parts = line.split(' ');
+    let (Some(method), Some(uri), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(HttpError::Error400);
+    };
+    if uri.is_empty() || !uri.bytes().all(|byte| byte.is_ascii_graphic()) {
+        return Err(HttpError::Error400);
+    }
+    Ok((
+        Method::parse(method)?,
+        uri.to_string(),
+        Version::parse(version)?,
+    ))
 }
 
-fn get_version(response: &str) -> String {
-    let mut splits = response.split_ascii_whitespace();
-    splits.next().unwrap();
-    splits.next().unwrap();
-    splits.next().unwrap().trim().to_string()
-}
-
-fn get_headers(response: &str) -> Vec<(String, String)> {
-    let mut headers: Vec<(String, String)> = Vec::new();
-    let mut lines = response.lines();
-    lines.next().unwrap();
-    for line in lines {
-        if line.contains(":") {
-            if let Some((key, value)) = line.split_once(":") {
-                headers.push((key.trim().to_string(), value.trim().to_string()));
-            }
-        }
-    }
-    headers
+/// Check the token grammar of RFC 9110: one or more visible characters other than the
+/// delimiters `"(),/:;<=>?@[\]{}`.
+pub(crate) fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte))
 }
 
 impl fmt::Display for Request {
@@ -124,9 +353,58 @@ impl fmt::Display for Request {
         for (key, value) in self.headers.iter() {
             request.push_str(&format!("{}: {}\r\n", key, value));
         }
-        if self.body.len() > 0 {
+        if !self.body.is_empty() {
             request.push_str(&format!("\r\n{}", String::from_utf8_lossy(&self.body)));
         }
         write!(f, "{}", request)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_request_line() {
+        let request = Request::parse("PROPFIND /files HTTP/1.0\r\nHost: localhost").unwrap();
+
+        assert_eq!(request.method, Method::Extension("PROPFIND".to_string()));
+        assert_eq!(request.uri, "/files");
+        assert_eq!(request.version, Version::Http10);
+        assert_eq!(request.get_value("Host"), Some("localhost"));
+    }
+
+    #[test]
+    fn it_should_prefer_absolute_form_authority_to_host() {
+        let request =
+            Request::parse("GET http://example.com/a%20b?x=1 HTTP/1.1\r\nHost: other").unwrap();
+
+        assert_eq!(request.host(), Some("example.com"));
+        assert_eq!(request.path(), "/a b");
+        assert_eq!(request.get_query("x"), Some("1"));
+    }
+
+    #[test]
+    fn it_should_reject_malformed_request_line() {
+        for head in [
+            "GET",
+            "GET /",
+            "GET  / HTTP/1.1",
+            "G(ET / HTTP/1.1",
+            "GET / HTTP/1",
+        ] {
+            assert!(
+                matches!(Request::parse(head), Err(HttpError::Error400)),
+                "{head}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_reject_unknown_version_with_505() {
+        assert!(matches!(
+            Request::parse("GET / HTTP/2.0"),
+            Err(HttpError::Error505)
+        ));
+    }
+}
@@ -2,29 +2,79 @@
 //!
 //! # Example:
 //! ```rust
-//! fn handle_client(_: Request) -> response {
-//!     Response::new(
-//!     200,
-//!     "Hello, World".as_bytes().to_vec(),
-//!     vec![("x-cookie".to_string(), "1234".to_string())],
-//!     ContentType::TextHtml]
-//!     )
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::cookie::SetCookie;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn handle_client(_: Request) -> Response {
+//!     let mut response = Response::new(
+//!         200,
+//!         "Hello, World".as_bytes().to_vec(),
+//!         vec![("Cache-Control".to_string(), "no-store".to_string())],
+//!         ContentType::TextHtml,
+//!     );
+//!     response.add_cookie(&SetCookie::new("session", "1234").with_http_only(true));
+//!     response
+//! }
+//! ```
+//!
+//! Responses can also be built step by step with [Response::builder], or with the shortcuts
+//! [Response::text], [Response::html], [Response::redirect] and [Response::no_content]. The
+//! builder only adds a `Content-Type` when one is given and leaves out the body and its
+//! length for the statuses that do not allow one, such as `204 No Content`.
+//!
+//! An invalid header or cookie, e.g. a value with a line break, is dropped so that it never
+//! reaches the client. [Response::try_set_header], [Response::try_add_cookie] and
+//! [ResponseBuilder::try_header] return the error instead.
+//!
+//! ```rust
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//! use webserv_rs::status::StatusCode;
+//!
+//! fn create(_: Request) -> Response {
+//!     Response::builder()
+//!         .status(StatusCode::CREATED)
+//!         .header("Location", "/items/1")
+//!         .content_type(ContentType::Json)
+//!         .body(r#"{"id":1}"#)
+//! }
+//!
+//! fn delete(_: Request) -> Response {
+//!     Response::no_content()
 //! }
 //! ```
-//!     
 use crate::content_type::ContentType;
+use crate::cookie::SetCookie;
+use crate::header_map::HeaderMap;
+use crate::http_error::HttpError;
+use crate::response_body::ResponseBody;
+use crate::socket::Socket;
+use crate::status::StatusCode;
 use chrono::prelude::*;
 
+/// Function run by the worker on the connection once a `101 Switching Protocols` response is
+/// sent. It receives the socket and the bytes already read after the request.
+pub type Upgrade = Box<dyn FnOnce(&mut dyn Socket, Vec<u8>) + Send>;
+
 #[allow(dead_code)]
 pub struct Response {
     pub version: String,
-    pub status: u32,
+    pub status: StatusCode,
     pub reason: String,
-    pub headers: Vec<(String, String)>,
+    pub headers: HeaderMap,
     pub body: Vec<u8>,
+    /// Trailer fields sent after a chunked body, see [crate::response_body].
+    pub trailers: HeaderMap,
+    pub upgrade: Option<Upgrade>,
+    pub(crate) stream: Option<ResponseBody>,
 }
 
 impl Response {
+    /// Response with the given status, body, headers and content type. A status that does not
+    /// have three digits gives a `500 Internal Server Error`, an invalid header is dropped.
     pub fn new(
         status: u32,
         body: Vec<u8>,
@@ -32,18 +82,102 @@ impl Response {
         content_type: ContentType,
     ) -> Self {
         let headers = make_headers(&headers, body.len(), content_type);
+        let status = StatusCode::from_u32(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
         Self {
             version: "HTTP/1.1".to_string(),
             status,
-            reason: reason_phrase(status),
+            reason: status.reason_phrase().to_string(),
             body,
             headers,
+            trailers: HeaderMap::new(),
+            upgrade: None,
+            stream: None,
         }
     }
 
+    /// Start building a `200 OK` response.
+    pub fn builder() -> ResponseBuilder {
+        ResponseBuilder {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// `200 OK` response with a `text/plain` body.
+    pub fn text(body: impl Into<String>) -> Self {
+        Self::builder()
+            .content_type(ContentType::Text)
+            .body(body.into())
+    }
+
+    /// `200 OK` response with a `text/html` body.
+    pub fn html(body: impl Into<String>) -> Self {
+        Self::builder()
+            .content_type(ContentType::TextHtml)
+            .body(body.into())
+    }
+
+    /// `302 Found` response to the given location. Use the builder for the other
+    /// redirections, e.g. `308 Permanent Redirect`.
+    pub fn redirect(location: &str) -> Self {
+        Self::builder()
+            .status(StatusCode::FOUND)
+            .header("Location", location)
+            .body(Vec::new())
+    }
+
+    /// `204 No Content` response.
+    pub fn no_content() -> Self {
+        Self::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Vec::new())
+    }
+
     pub fn is_error_status(&self) -> bool {
-        self.status >= 400
+        self.status.is_client_error() || self.status.is_server_error()
+    }
+
+    // Retrieve the first value of the given header.
+    pub fn get_value(&self, key: &str) -> Option<&str> {
+        self.headers.get(key)
     }
+
+    /// Replace every header with the given name by a single value. An invalid header is
+    /// dropped so that it never reaches the client, see [Response::try_set_header].
+    pub fn set_header(&mut self, key: &str, value: &str) {
+        let _ = self.try_set_header(key, value);
+    }
+
+    /// Replace every header with the given name by a single value, failing with
+    /// `HttpError::InvalidHeader` when the name or the value is invalid.
+    pub fn try_set_header(&mut self, key: &str, value: &str) -> Result<(), HttpError> {
+        self.headers.insert(key, value)
+    }
+
+    /// Add a `Set-Cookie` header, keeping the cookies already set. An invalid cookie is
+    /// dropped, see [Response::try_add_cookie].
+    pub fn add_cookie(&mut self, cookie: &SetCookie) {
+        let _ = self.try_add_cookie(cookie);
+    }
+
+    /// Add a `Set-Cookie` header, keeping the cookies already set, failing with
+    /// `HttpError::InvalidHeader` when the cookie does not pass [SetCookie::validate].
+    pub fn try_add_cookie(&mut self, cookie: &SetCookie) -> Result<(), HttpError> {
+        cookie.validate()?;
+        self.headers.append("Set-Cookie", &cookie.to_string())
+    }
+
+    // Ask the worker to close the connection once this response is sent.
+    pub fn close_connection(&mut self) {
+        self.set_header("Connection", "close");
+    }
+
+    pub fn is_close(&self) -> bool {
+        self.headers.has_token("Connection", "close")
+    }
+
+    /// Head and buffered body of the response. A streamed body is only sent by
+    /// [Response::write_to].
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes_str = String::new();
         bytes_str.push_str(&self.make_first_line());
@@ -68,91 +202,180 @@ impl Response {
     }
 }
 
+/// Builder of a [Response], see [Response::builder].
+pub struct ResponseBuilder {
+    status: StatusCode,
+    headers: HeaderMap,
+}
+
+impl ResponseBuilder {
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Add a header, keeping the ones with the same name. An invalid header is dropped so
+    /// that it never reaches the client, see [ResponseBuilder::try_header].
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        let _ = self.headers.append(key, value);
+        self
+    }
+
+    /// Add a header, keeping the ones with the same name, failing with
+    /// `HttpError::InvalidHeader` when the name or the value is invalid.
+    pub fn try_header(mut self, key: &str, value: &str) -> Result<Self, HttpError> {
+        self.headers.append(key, value)?;
+        Ok(self)
+    }
+
+    /// Set the `Content-Type`. An invalid `ContentType::Image` subtype is dropped.
+    pub fn content_type(mut self, content_type: ContentType) -> Self {
+        let _ = self
+            .headers
+            .insert("Content-Type", &content_type.to_string());
+        self
+    }
+
+    /// Build the response with the given body, adding the `Content-length`, `Date` and
+    /// `Server` headers when they are not set. The body is dropped when the status does not
+    /// allow one.
+    pub fn body(self, body: impl Into<Vec<u8>>) -> Response {
+        let mut body = body.into();
+        let mut headers = self.headers;
+        let mut defaults = vec![
+            ("Date", get_header_date()),
+            ("Server", "webserv-rs".to_string()),
+        ];
+        if self.status.allows_body() {
+            defaults.insert(0, ("Content-length", body.len().to_string()));
+        } else {
+            body.clear();
+        }
+        for (key, value) in defaults {
+            if !headers.contains(key) {
+                let _ = headers.append(key, &value);
+            }
+        }
+        Response {
+            version: "HTTP/1.1".to_string(),
+            status: self.status,
+            reason: self.status.reason_phrase().to_string(),
+            headers,
+            body,
+            trailers: HeaderMap::new(),
+            upgrade: None,
+            stream: None,
+        }
+    }
+
+    /// Build the response with a streamed body, see [crate::response_body]. The body is
+    /// dropped when the status does not allow one.
+    pub fn stream(self, body: ResponseBody) -> Response {
+        let allows_body = self.status.allows_body();
+        let mut response = self.body(Vec::new());
+        if allows_body {
+            response.set_body(body);
+        }
+        response
+    }
+}
+
 fn make_headers(
     headers: &[(String, String)],
     body_len: usize,
     content_type: ContentType,
-) -> Vec<(String, String)> {
-    let mut retval = Vec::new();
-
-    for header in headers {
-        retval.push(header.clone());
+) -> HeaderMap {
+    let mut retval = HeaderMap::new();
+    let defaults = [
+        ("Content-length", body_len.to_string()),
+        ("Accept-Encoding", "".to_string()),
+        ("Content-Type", content_type.to_string()),
+        ("Date", get_header_date()),
+        ("Server", "webserv-rs".to_string()),
+    ];
+    let headers = headers
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()));
+    for (key, value) in headers.chain(defaults.iter().map(|(key, value)| (*key, value.as_str()))) {
+        let _ = retval.append(key, value);
     }
-
-    retval.push(("Content-length".to_string(), format!("{}", body_len)));
-    retval.push(("Accept-Encoding".to_string(), "".to_string()));
-    retval.push(("Connection".to_string(), "Keep-Alive".to_string()));
-    retval.push(("Content-Type".to_string(), format!("{}", content_type)));
-    retval.push(("Date".to_string(), get_header_date()));
-    retval.push(("Server".to_string(), "webserv-rs".to_string()));
-
     retval
 }
 
 fn get_header_date() -> String {
     let now: DateTime<Utc> = Utc::now();
-    format!("{}", now.format("%A, %d %m %Y %H:%M:%S GMT"))
+    format!("{}", now.format("%a, %d %b %Y %H:%M:%S GMT"))
 }
 
-fn reason_phrase(status: u32) -> String {
-    match status {
-        // 1xx Informational
-        100 => "Continue".to_string(),
-        101 => "Switching Protocols".to_string(),
-
-        // 2xx Success
-        200 => "OK".to_string(),
-        201 => "Created".to_string(),
-        202 => "Accepted".to_string(),
-        203 => "Non-Authoritative Information".to_string(),
-        204 => "No Content".to_string(),
-        205 => "Reset Content".to_string(),
-        206 => "Partial Content".to_string(),
-
-        // 3xx Redirection
-        300 => "Multiple Choices".to_string(),
-        301 => "Moved Permanently".to_string(),
-        302 => "Found".to_string(),
-        303 => "See Other".to_string(),
-        304 => "Not Modified".to_string(),
-        305 => "Use Proxy".to_string(),
-        306 => "(Unused)".to_string(),
-        307 => "Temporary Redirect".to_string(),
-        308 => "Permanent Redirect".to_string(),
-
-        // 4xx Client Error
-        400 => "Bad Request".to_string(),
-        401 => "Unauthorized".to_string(),
-        402 => "Payment Required".to_string(),
-        403 => "Forbidden".to_string(),
-        404 => "Not Found".to_string(),
-        405 => "Method Not Allowed".to_string(),
-        406 => "Not Acceptable".to_string(),
-        407 => "Proxy Authentication Required".to_string(),
-        408 => "Request Timeout".to_string(),
-        409 => "Conflict".to_string(),
-        410 => "Gone".to_string(),
-        411 => "Length Required".to_string(),
-        412 => "Precondition Failed".to_string(),
-        413 => "Content Too Large".to_string(),
-        414 => "URI Too Long".to_string(),
-        415 => "Unsupported Media Type".to_string(),
-        416 => "Range Not Satisfiable".to_string(),
-        417 => "Expectation Failed".to_string(),
-        418 => "I'm a tea pot".to_string(),
-        421 => "Misdirected Request".to_string(),
-        422 => "Unprocessable Content".to_string(),
-        426 => "Upgrade Required".to_string(),
-
-        // 5xx Server Error
-        500 => "Internal Server Error".to_string(),
-        501 => "Not Implemented".to_string(),
-        502 => "Bad Gateway".to_string(),
-        503 => "Service Unavailable".to_string(),
-        504 => "Gateway Timeout".to_string(),
-        505 => "HTTP Version Not Supported".to_string(),
-
-        // Default
-        _ => "Unknown Status Code".to_string(),
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_build_response() {
+        let response = Response::builder()
+            .status(StatusCode::CREATED)
+            .header("Location", "/items/1")
+            .header("Bad Name", "dropped")
+            .content_type(ContentType::Json)
+            .body("{}");
+
+        assert_eq!(response.status, 201);
+        assert_eq!(response.reason, "Created");
+        assert_eq!(response.get_value("Location"), Some("/items/1"));
+        assert_eq!(response.get_value("Content-Type"), Some("application/json"));
+        assert_eq!(response.get_value("Content-Length"), Some("2"));
+        assert_eq!(response.headers.len(), 5);
+    }
+
+    #[test]
+    fn it_should_report_invalid_headers_and_cookies() {
+        let mut response = Response::text("Hello");
+        assert!(response
+            .try_set_header("Location", "/a\r\nX-Injected: 1")
+            .is_err());
+        assert!(response
+            .try_add_cookie(&SetCookie::new("bad name", "1"))
+            .is_err());
+        assert!(response.try_set_header("Location", "/a").is_ok());
+        assert!(!response.headers.contains("Set-Cookie"));
+
+        assert!(Response::builder().try_header("Bad Name", "1").is_err());
+        let builder = Response::builder().try_header("Location", "/a").unwrap();
+        assert_eq!(builder.body("").get_value("Location"), Some("/a"));
+    }
+
+    #[test]
+    fn it_should_leave_out_body_when_status_forbids_it() {
+        let response = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .body("ignored");
+        assert!(response.body.is_empty());
+        assert!(!response.headers.contains("Content-Length"));
+        assert!(!response.headers.contains("Content-Type"));
+
+        let response = Response::redirect("/login");
+        assert_eq!(response.status, StatusCode::FOUND);
+        assert_eq!(response.get_value("Location"), Some("/login"));
+    }
+
+    #[test]
+    fn it_should_replace_invalid_status_with_500() {
+        for status in [42, 1000] {
+            let response = Response::new(status, vec![], vec![], ContentType::Text);
+            assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+            assert_eq!(
+                response.make_first_line(),
+                "HTTP/1.1 500 Internal Server Error\r\n"
+            );
+        }
+    }
+
+    #[test]
+    fn it_should_format_date_as_imf_fixdate() {
+        let date = get_header_date();
+
+        assert!(DateTime::parse_from_rfc2822(&date.replace("GMT", "+0000")).is_ok());
+        assert_eq!(date.len(), "Sun, 06 Nov 1994 08:49:37 GMT".len());
     }
 }
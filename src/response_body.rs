@@ -0,0 +1,308 @@
+//! Streamed response bodies
+//!
+//! A [Response] body does not have to be held in memory: a [ResponseBody] can be a byte
+//! buffer, a file, any reader or an iterator of chunks, set with [Response::set_body] or
+//! [ResponseBuilder::stream](crate::response::ResponseBuilder::stream). The body is read
+//! while the response is written to the connection.
+//!
+//! A body whose length is known is sent with a `Content-Length`. Otherwise the response is
+//! sent with `Transfer-Encoding: chunked`, which is also used for the responses with
+//! `trailers`, the trailer names being announced in the `Trailer` header. HTTP/1.0 clients do
+//! not support chunked bodies: a body of unknown length is sent as is and delimited by the
+//! end of the connection, and the trailers are dropped.
+//!
+//! A reader that ends before giving the length it was created with makes the write fail, so
+//! that the connection is closed instead of leaving the client waiting for the missing bytes.
+//!
+//! The event loop reads streamed bodies into memory on the thread pool before sending them.
+//!
+//! # Example
+//! ```rust,no_run
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//! use webserv_rs::response_body::ResponseBody;
+//! use webserv_rs::status::StatusCode;
+//!
+//! fn download(_: Request) -> Response {
+//!     match ResponseBody::file("./static/video.mp4") {
+//!         Ok(body) => Response::builder().stream(body),
+//!         Err(_) => Response::builder().status(StatusCode::NOT_FOUND).body(Vec::new()),
+//!     }
+//! }
+//!
+//! fn events(_: Request) -> Response {
+//!     let lines = (0..3).map(|i| format!("event {i}\n").into_bytes());
+//!     let mut response = Response::builder().stream(ResponseBody::chunks(lines));
+//!     response.trailers.append("X-Events", "3").unwrap();
+//!     response
+//! }
+//! ```
+use crate::header_map::HeaderMap;
+use crate::request::Version;
+use crate::response::Response;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+
+pub enum ResponseBody {
+    Bytes(Vec<u8>),
+    /// Reader with the number of bytes it gives, if known.
+    Reader {
+        reader: Box<dyn Read + Send>,
+        length: Option<u64>,
+    },
+    Chunks(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+}
+
+impl ResponseBody {
+    /// Body read from a file, with the size of the file as length.
+    pub fn file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        Ok(Self::sized_reader(file, length))
+    }
+
+    /// Body read from a reader until its end, sent chunked.
+    pub fn reader(reader: impl Read + Send + 'static) -> Self {
+        Self::Reader {
+            reader: Box::new(reader),
+            length: None,
+        }
+    }
+
+    /// Body made of the first `length` bytes of a reader.
+    pub fn sized_reader(reader: impl Read + Send + 'static, length: u64) -> Self {
+        Self::Reader {
+            reader: Box::new(reader.take(length)),
+            length: Some(length),
+        }
+    }
+
+    /// Body sent chunk by chunk, as the iterator gives them.
+    pub fn chunks<I>(chunks: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        Self::Chunks(Box::new(chunks.into_iter()))
+    }
+
+    /// Length of the body, `None` when it is only known once the body is read.
+    pub fn content_length(&self) -> Option<u64> {
+        match self {
+            ResponseBody::Bytes(bytes) => Some(bytes.len() as u64),
+            ResponseBody::Reader { length, .. } => *length,
+            ResponseBody::Chunks(_) => None,
+        }
+    }
+
+    // Write the body, each write of the reader or each item of the iterator being a chunk
+    // when the writer is a `ChunkedWriter`. Fail when the reader gives less than its length.
+    fn write_to(self, writer: &mut dyn Write) -> io::Result<()> {
+        match self {
+            ResponseBody::Bytes(bytes) => writer.write_all(&bytes),
+            ResponseBody::Reader { mut reader, length } => {
+                let written = io::copy(&mut reader, writer)?;
+                if length.is_some_and(|length| written < length) {
+                    return Err(io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!("response body ended after {written} bytes"),
+                    ));
+                }
+                Ok(())
+            }
+            ResponseBody::Chunks(chunks) => chunks
+                .into_iter()
+                .try_for_each(|chunk| writer.write_all(&chunk)),
+        }
+    }
+}
+
+impl From<Vec<u8>> for ResponseBody {
+    fn from(bytes: Vec<u8>) -> Self {
+        ResponseBody::Bytes(bytes)
+    }
+}
+
+impl From<File> for ResponseBody {
+    fn from(file: File) -> Self {
+        match file.metadata() {
+            Ok(metadata) => ResponseBody::sized_reader(file, metadata.len()),
+            Err(_) => ResponseBody::reader(file),
+        }
+    }
+}
+
+/// Writer encoding what is written with the chunked transfer coding, one chunk per write.
+pub(crate) struct ChunkedWriter<'a> {
+    inner: &'a mut dyn Write,
+}
+
+impl<'a> ChunkedWriter<'a> {
+    pub(crate) fn new(inner: &'a mut dyn Write) -> Self {
+        Self { inner }
+    }
+
+    /// Write the last chunk and the trailer section.
+    pub(crate) fn finish(self, trailers: &HeaderMap) -> io::Result<()> {
+        let mut end = String::from("0\r\n");
+        for (name, value) in trailers {
+            end.push_str(&format!("{name}: {value}\r\n"));
+        }
+        end.push_str("\r\n");
+        self.inner.write_all(end.as_bytes())
+    }
+}
+
+impl Write for ChunkedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An empty chunk would end the body.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.inner
+            .write_all(format!("{:x}\r\n", buf.len()).as_bytes())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Response {
+    /// Replace the body, updating `Content-length`. A byte buffer is kept in `body`, the other
+    /// bodies are read when the response is written.
+    pub fn set_body(&mut self, body: impl Into<ResponseBody>) {
+        let body = body.into();
+        self.headers.remove("Content-Length");
+        if let Some(length) = body.content_length() {
+            self.set_header("Content-length", &length.to_string());
+        }
+        match body {
+            ResponseBody::Bytes(bytes) => {
+                self.body = bytes;
+                self.stream = None;
+            }
+            body => {
+                self.body.clear();
+                self.stream = Some(body);
+            }
+        }
+    }
+
+    /// True when the body is sent with the chunked transfer coding.
+    pub fn is_chunked(&self) -> bool {
+        self.headers.has_token("Transfer-Encoding", "chunked")
+    }
+
+    /// Choose how the body is delimited for a client using the given version, see
+    /// [crate::response_body]. Return true when the end of the body is the end of the
+    /// connection, which must then be closed.
+    pub(crate) fn set_framing(&mut self, version: Version) -> bool {
+        if !self.status.allows_body() {
+            return false;
+        }
+        let unknown_length = self
+            .stream
+            .as_ref()
+            .is_some_and(|body| body.content_length().is_none());
+        if !unknown_length && self.trailers.is_empty() {
+            return false;
+        }
+        if version == Version::Http10 {
+            self.trailers = HeaderMap::new();
+            return unknown_length;
+        }
+        self.headers.remove("Content-Length");
+        self.set_header("Transfer-Encoding", "chunked");
+        if !self.trailers.is_empty() {
+            let names: Vec<&str> = self.trailers.iter().map(|(name, _)| name).collect();
+            let names = names.join(", ");
+            self.set_header("Trailer", &names);
+        }
+        false
+    }
+
+    /// Read a streamed body into `body`, so that the response is written without blocking.
+    #[cfg(feature = "event-loop")]
+    pub(crate) fn buffer_body(&mut self) -> io::Result<()> {
+        let Some(stream) = self.stream.take() else {
+            return Ok(());
+        };
+        let mut body = Vec::new();
+        stream.write_to(&mut body)?;
+        self.set_body(body);
+        Ok(())
+    }
+
+    /// Write the response, reading its body if it is streamed.
+    pub fn write_to(&mut self, writer: &mut dyn Write) -> io::Result<()> {
+        let mut head = self.make_first_line();
+        head.push_str(&self.make_headers());
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())?;
+        let body = match self.stream.take() {
+            Some(stream) => stream,
+            None => ResponseBody::Bytes(std::mem::take(&mut self.body)),
+        };
+        if !self.is_chunked() {
+            return body.write_to(writer);
+        }
+        let mut chunked = ChunkedWriter::new(writer);
+        body.write_to(&mut chunked)?;
+        chunked.finish(&self.trailers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_type::ContentType;
+
+    fn write(mut response: Response, version: Version) -> String {
+        response.set_framing(version);
+        let mut output = Vec::new();
+        response.write_to(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        output.split_once("\r\n\r\n").unwrap().1.to_string()
+    }
+
+    #[test]
+    fn it_should_send_unknown_length_chunked() {
+        let chunks = vec![b"Hello".to_vec(), Vec::new(), b" World".to_vec()];
+        let mut response = Response::builder().stream(ResponseBody::chunks(chunks));
+        response.trailers.append("Checksum", "42").unwrap();
+
+        assert_eq!(
+            write(response, Version::Http11),
+            "5\r\nHello\r\n6\r\n World\r\n0\r\nChecksum: 42\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn it_should_send_known_length_as_is() {
+        let reader = ResponseBody::sized_reader(&b"Hello World"[..5], 5);
+        let response = Response::builder().stream(reader);
+        assert_eq!(response.get_value("Content-Length"), Some("5"));
+        assert_eq!(write(response, Version::Http11), "Hello");
+
+        let mut response = Response::new(200, Vec::new(), vec![], ContentType::Text);
+        response.set_body(ResponseBody::reader(&b"Hello"[..]));
+        assert!(response.set_framing(Version::Http10));
+        assert!(!response.headers.contains("Content-Length"));
+    }
+
+    #[test]
+    fn it_should_fail_when_reader_ends_early() {
+        let mut response = Response::builder().stream(ResponseBody::sized_reader(&b"Hi"[..], 5));
+        response.set_framing(Version::Http11);
+        let mut output = Vec::new();
+
+        let error = response.write_to(&mut output).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert!(output.ends_with(b"\r\n\r\nHi"));
+    }
+}
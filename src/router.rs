@@ -0,0 +1,256 @@
+//! Router module
+//!
+//! A [Router] dispatches requests to handlers according to their method and path. Route
+//! patterns are made of segments separated by `/`:
+//! * a static segment must match exactly: `/users`
+//! * a parameter segment starts with `:` and matches one segment: `/users/:id`
+//! * a wildcard segment starts with `*` and matches the rest of the path: `/assets/*path`
+//!
+//! Routes are matched against the decoded path of the request, without its query. Each
+//! segment is matched once decoded, so `/users/a%2Fb` matches `/users/:id` with `a/b` as `id`.
+//! Captured values are stored in the [Request] `params`. When no route matches the path,
+//! the router answers `404 Not Found`. When the path matches but not the method, it
+//! answers `405 Method Not Allowed` with an `Allow` header.
+//!
+//! WebSocket handlers are registered on a path with [Router::websocket].
+//!
+//! The router is itself a [Handler] and can be given to `HttpServer::run`.
+//!
+//! # Example
+//! ```rust
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//! use webserv_rs::router::Router;
+//!
+//! fn get_user(request: Request) -> Response {
+//!     let id = request.get_param("id").unwrap_or_default().to_string();
+//!     Response::new(200, id.into_bytes(), vec![], ContentType::Text)
+//! }
+//!
+//! let mut router = Router::new();
+//! router.get("/users/:id", get_user);
+//! ```
+use crate::handler::Handler;
+use crate::http_error::error_response;
+use crate::request::{Method, Request};
+use crate::response::Response;
+use crate::uri::percent_decode;
+use crate::websocket::{self, WebSocket};
+use std::sync::Arc;
+
+enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+struct Route {
+    method: Method,
+    segments: Vec<Segment>,
+    handler: Box<dyn Handler>,
+}
+
+impl Route {
+    fn new(method: Method, pattern: &str, handler: Box<dyn Handler>) -> Self {
+        let segments = split_path(pattern)
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Static(segment.to_string())
+                }
+            })
+            .collect();
+        Self {
+            method,
+            segments,
+            handler,
+        }
+    }
+
+    // Return the captured parameters if the path matches the route pattern.
+    fn match_path(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let mut params = Vec::new();
+        let parts = split_path(path)
+            .map(|part| percent_decode(part, false))
+            .collect::<Result<Vec<String>, _>>()
+            .ok()?;
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Static(expected) => {
+                    if parts.get(i) != Some(expected) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => {
+                    params.push((name.clone(), parts.get(i)?.clone()));
+                }
+                Segment::Wildcard(name) => {
+                    let rest = parts.get(i..).unwrap_or_default().join("/");
+                    params.push((name.clone(), rest));
+                    return Some(params);
+                }
+            }
+        }
+        if parts.len() == self.segments.len() {
+            Some(params)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler for a method and a path pattern. Routes are tried in the order
+    /// they were added.
+    pub fn route<H: Handler>(&mut self, method: Method, pattern: &str, handler: H) -> &mut Self {
+        self.routes
+            .push(Route::new(method, pattern, Box::new(handler)));
+        self
+    }
+
+    pub fn get<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn patch<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.route(Method::Patch, pattern, handler)
+    }
+
+    pub fn delete<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    /// Register a WebSocket handler. Requests on this path are upgraded to the WebSocket
+    /// protocol and the handler receives the connection.
+    pub fn websocket<F>(&mut self, pattern: &str, handler: F) -> &mut Self
+    where
+        F: Fn(WebSocket, Request) + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.get(pattern, move |request: Request| {
+            let handler = Arc::clone(&handler);
+            websocket::upgrade(request, move |websocket, request| {
+                handler(websocket, request)
+            })
+        })
+    }
+}
+
+impl Handler for Router {
+    fn handle(&self, mut request: Request) -> Response {
+        let path = request.path().to_string();
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in self.routes.iter() {
+            if let Some(params) = route.match_path(&path) {
+                if route.method == request.method {
+                    request.params = params;
+                    return route.handler.handle(request);
+                }
+                if !allowed.contains(&route.method.as_str()) {
+                    allowed.push(route.method.as_str());
+                }
+            }
+        }
+        if allowed.is_empty() {
+            error_response(404)
+        } else {
+            let mut response = error_response(405);
+            response.set_header("Allow", &allowed.join(", "));
+            response
+        }
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_type::ContentType;
+
+    fn echo_params(request: Request) -> Response {
+        let params = request
+            .params
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<String>>()
+            .join("&");
+        Response::new(200, params.into_bytes(), vec![], ContentType::Text)
+    }
+
+    fn get_router() -> Router {
+        let mut router = Router::new();
+        router
+            .get("/", echo_params)
+            .get("/users/:id", echo_params)
+            .delete("/users/:id", echo_params)
+            .get("/assets/*path", echo_params);
+        router
+    }
+
+    fn get_request(method: &str, uri: &str) -> Request {
+        Request::parse(&format!("{method} {uri} HTTP/1.1\r\nHost: localhost")).unwrap()
+    }
+
+    #[test]
+    fn it_should_capture_path_parameters() {
+        let response = get_router().handle(get_request("GET", "/users/42?verbose=1"));
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"id=42");
+    }
+
+    #[test]
+    fn it_should_capture_wildcard() {
+        let response = get_router().handle(get_request("GET", "/assets/css/main.css"));
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"path=css/main.css");
+    }
+
+    #[test]
+    fn it_should_keep_encoded_slash_in_parameter() {
+        let response = get_router().handle(get_request("GET", "/users/a%2Fb%2525"));
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"id=a/b%25");
+    }
+
+    #[test]
+    fn it_should_answer_404_for_unknown_path() {
+        let response = get_router().handle(get_request("GET", "/users/42/posts"));
+
+        assert_eq!(response.status, 404);
+    }
+
+    #[test]
+    fn it_should_answer_405_with_allowed_methods() {
+        let response = get_router().handle(get_request("POST", "/users/42"));
+
+        assert_eq!(response.status, 405);
+        assert_eq!(response.get_value("Allow"), Some("GET, DELETE"));
+    }
+}
@@ -0,0 +1,183 @@
+//! Shutdown module
+//!
+//! A [ShutdownHandle] stops an [HttpServer](crate::http_server::HttpServer) gracefully. Once
+//! triggered, the server stops accepting connections, in-flight requests are answered,
+//! keep-alive connections waiting for their next request are closed and `run` returns when
+//! every worker is done or when the shutdown timeout expires.
+//!
+//! The handle can be cloned and sent to another thread. With [ShutdownHandle::listen_signals],
+//! SIGTERM and SIGINT trigger the shutdown.
+//!
+//! The accept loop blocks in `accept` and is woken by the handle, which connects to the
+//! listening address once the shutdown is triggered.
+//!
+//! # Example
+//! ```rust,no_run
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::http_server::HttpServer;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//!
+//! fn handle_client(_: Request) -> Response {
+//!     Response::new(200, vec![], vec![], ContentType::Text)
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let mut server = HttpServer::new("127.0.0.1", 8080)?;
+//!     server.shutdown_handle().listen_signals()?;
+//!     server.run(handle_client)
+//! }
+//! ```
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const WAKE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct Inner {
+    stopping: Arc<AtomicBool>,
+    next_id: AtomicUsize,
+    connections: Mutex<Vec<(usize, TcpStream, Arc<AtomicBool>)>>,
+    // Addresses of the listeners blocked in `accept`.
+    listeners: Mutex<Vec<SocketAddr>>,
+}
+
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<Inner>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop accepting connections and close the idle keep-alive connections. Connections
+    /// processing a request are closed after their response is sent.
+    pub fn shutdown(&self) {
+        self.inner.stopping.store(true, Ordering::SeqCst);
+        self.wake_listeners();
+        self.close_idle();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Trigger the shutdown when the process receives SIGTERM or SIGINT. The signals are
+    /// received on a dedicated thread.
+    pub fn listen_signals(&self) -> std::io::Result<()> {
+        let mut signals = Signals::new([SIGTERM, SIGINT])?;
+        let handle = self.clone();
+        thread::spawn(move || {
+            for _ in signals.forever() {
+                handle.shutdown();
+            }
+        });
+        Ok(())
+    }
+
+    /// Wake the accept loop of this listener when the shutdown is triggered.
+    pub(crate) fn register_listener(&self, addr: SocketAddr) {
+        if let Ok(mut listeners) = self.inner.listeners.lock() {
+            listeners.push(addr);
+        }
+    }
+
+    pub(crate) fn unregister_listener(&self, addr: SocketAddr) {
+        if let Ok(mut listeners) = self.inner.listeners.lock() {
+            if let Some(index) = listeners.iter().position(|listener| *listener == addr) {
+                listeners.remove(index);
+            }
+        }
+    }
+
+    // Connect to each listener so that its blocking `accept` returns.
+    fn wake_listeners(&self) {
+        let Ok(listeners) = self.inner.listeners.lock() else {
+            return;
+        };
+        for addr in listeners.iter() {
+            let mut addr = *addr;
+            if addr.ip().is_unspecified() {
+                addr.set_ip(match addr.ip() {
+                    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                });
+            }
+            if let Err(e) = TcpStream::connect_timeout(&addr, WAKE_TIMEOUT) {
+                eprintln!("Error while waking accept loop on {addr}: {e}");
+            }
+        }
+    }
+
+    pub(crate) fn close_idle(&self) {
+        if let Ok(connections) = self.inner.connections.lock() {
+            for (_, stream, idle) in connections.iter() {
+                if idle.load(Ordering::SeqCst) {
+                    let _ = stream.shutdown(Shutdown::Read);
+                }
+            }
+        }
+    }
+
+    /// Close every connection, even the ones processing a request.
+    pub(crate) fn close_all(&self) {
+        if let Ok(connections) = self.inner.connections.lock() {
+            for (_, stream, _) in connections.iter() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    pub(crate) fn register(&self, stream: &TcpStream) -> std::io::Result<Connection> {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let idle = Arc::new(AtomicBool::new(false));
+        if let Ok(mut connections) = self.inner.connections.lock() {
+            connections.push((id, stream.try_clone()?, Arc::clone(&idle)));
+        }
+        Ok(Connection {
+            id,
+            idle,
+            handle: self.clone(),
+        })
+    }
+}
+
+/// State of a connection registered to a [ShutdownHandle]. It is unregistered on drop.
+pub struct Connection {
+    id: usize,
+    idle: Arc<AtomicBool>,
+    handle: ShutdownHandle,
+}
+
+impl Connection {
+    /// Mark the connection as waiting for a new request. Return false if the server is
+    /// shutting down and the connection must be closed.
+    pub fn wait_request(&self) -> bool {
+        self.idle.store(true, Ordering::SeqCst);
+        !self.handle.is_shutdown()
+    }
+
+    /// Mark the connection as processing a request.
+    pub fn start_request(&self) {
+        self.idle.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.handle.is_shutdown()
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.handle.inner.connections.lock() {
+            connections.retain(|(id, _, _)| *id != self.id);
+        }
+    }
+}
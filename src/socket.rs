@@ -0,0 +1,23 @@
+//! Socket trait
+//!
+//! A [Worker](crate::worker::Worker) reads requests from and writes responses to any
+//! stream implementing [Socket]. Besides `Read` and `Write`, the stream must accept read
+//! and write timeouts so that the worker can enforce its [Timeouts](crate::worker::Timeouts).
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+pub trait Socket: Read + Write {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+}
+
+impl Socket for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
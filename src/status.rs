@@ -0,0 +1,254 @@
+//! HTTP status codes
+//!
+//! [StatusCode] holds a three-digit status code. The codes registered by RFC 9110 have a
+//! constant, e.g. [StatusCode::NOT_FOUND], and a reason phrase. Other codes in the
+//! `100..=999` range can be used with [StatusCode::from_u32], their class is given by their
+//! first digit.
+//!
+//! A status code compares with a number, so `response.status == 200` works.
+//!
+//! # Example
+//! ```rust
+//! use webserv_rs::status::StatusCode;
+//!
+//! assert_eq!(StatusCode::NOT_FOUND.as_u32(), 404);
+//! assert_eq!(StatusCode::NOT_FOUND.reason_phrase(), "Not Found");
+//! assert!(StatusCode::NOT_FOUND.is_client_error());
+//!
+//! let custom = StatusCode::from_u32(299).unwrap();
+//! assert!(custom.is_success());
+//! assert!(StatusCode::from_u32(1000).is_none());
+//! ```
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StatusCode(u32);
+
+impl StatusCode {
+    // 1xx Informational
+    pub const CONTINUE: StatusCode = StatusCode(100);
+    pub const SWITCHING_PROTOCOLS: StatusCode = StatusCode(101);
+    pub const PROCESSING: StatusCode = StatusCode(102);
+    pub const EARLY_HINTS: StatusCode = StatusCode(103);
+
+    // 2xx Success
+    pub const OK: StatusCode = StatusCode(200);
+    pub const CREATED: StatusCode = StatusCode(201);
+    pub const ACCEPTED: StatusCode = StatusCode(202);
+    pub const NON_AUTHORITATIVE_INFORMATION: StatusCode = StatusCode(203);
+    pub const NO_CONTENT: StatusCode = StatusCode(204);
+    pub const RESET_CONTENT: StatusCode = StatusCode(205);
+    pub const PARTIAL_CONTENT: StatusCode = StatusCode(206);
+
+    // 3xx Redirection
+    pub const MULTIPLE_CHOICES: StatusCode = StatusCode(300);
+    pub const MOVED_PERMANENTLY: StatusCode = StatusCode(301);
+    pub const FOUND: StatusCode = StatusCode(302);
+    pub const SEE_OTHER: StatusCode = StatusCode(303);
+    pub const NOT_MODIFIED: StatusCode = StatusCode(304);
+    pub const USE_PROXY: StatusCode = StatusCode(305);
+    pub const TEMPORARY_REDIRECT: StatusCode = StatusCode(307);
+    pub const PERMANENT_REDIRECT: StatusCode = StatusCode(308);
+
+    // 4xx Client Error
+    pub const BAD_REQUEST: StatusCode = StatusCode(400);
+    pub const UNAUTHORIZED: StatusCode = StatusCode(401);
+    pub const PAYMENT_REQUIRED: StatusCode = StatusCode(402);
+    pub const FORBIDDEN: StatusCode = StatusCode(403);
+    pub const NOT_FOUND: StatusCode = StatusCode(404);
+    pub const METHOD_NOT_ALLOWED: StatusCode = StatusCode(405);
+    pub const NOT_ACCEPTABLE: StatusCode = StatusCode(406);
+    pub const PROXY_AUTHENTICATION_REQUIRED: StatusCode = StatusCode(407);
+    pub const REQUEST_TIMEOUT: StatusCode = StatusCode(408);
+    pub const CONFLICT: StatusCode = StatusCode(409);
+    pub const GONE: StatusCode = StatusCode(410);
+    pub const LENGTH_REQUIRED: StatusCode = StatusCode(411);
+    pub const PRECONDITION_FAILED: StatusCode = StatusCode(412);
+    pub const CONTENT_TOO_LARGE: StatusCode = StatusCode(413);
+    pub const URI_TOO_LONG: StatusCode = StatusCode(414);
+    pub const UNSUPPORTED_MEDIA_TYPE: StatusCode = StatusCode(415);
+    pub const RANGE_NOT_SATISFIABLE: StatusCode = StatusCode(416);
+    pub const EXPECTATION_FAILED: StatusCode = StatusCode(417);
+    pub const IM_A_TEAPOT: StatusCode = StatusCode(418);
+    pub const MISDIRECTED_REQUEST: StatusCode = StatusCode(421);
+    pub const UNPROCESSABLE_CONTENT: StatusCode = StatusCode(422);
+    pub const UPGRADE_REQUIRED: StatusCode = StatusCode(426);
+    pub const PRECONDITION_REQUIRED: StatusCode = StatusCode(428);
+    pub const TOO_MANY_REQUESTS: StatusCode = StatusCode(429);
+    pub const REQUEST_HEADER_FIELDS_TOO_LARGE: StatusCode = StatusCode(431);
+
+    // 5xx Server Error
+    pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode(500);
+    pub const NOT_IMPLEMENTED: StatusCode = StatusCode(501);
+    pub const BAD_GATEWAY: StatusCode = StatusCode(502);
+    pub const SERVICE_UNAVAILABLE: StatusCode = StatusCode(503);
+    pub const GATEWAY_TIMEOUT: StatusCode = StatusCode(504);
+    pub const HTTP_VERSION_NOT_SUPPORTED: StatusCode = StatusCode(505);
+
+    /// Status code from its number, `None` when it does not have three digits.
+    pub fn from_u32(code: u32) -> Option<Self> {
+        (100..=999).contains(&code).then_some(Self(code))
+    }
+
+    // Status code from a number that was not checked, as given to `Response::new`.
+    pub(crate) fn new_unchecked(code: u32) -> Self {
+        Self(code)
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.0)
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.0)
+    }
+
+    /// False for the statuses whose responses never have a body: `1xx`, `204 No Content` and
+    /// `304 Not Modified`.
+    pub fn allows_body(&self) -> bool {
+        !self.is_informational() && *self != Self::NO_CONTENT && *self != Self::NOT_MODIFIED
+    }
+
+    /// Reason phrase of a registered status code, `Unknown Status Code` for the others.
+    pub fn reason_phrase(&self) -> &'static str {
+        match self.0 {
+            // 1xx Informational
+            100 => "Continue",
+            101 => "Switching Protocols",
+            102 => "Processing",
+            103 => "Early Hints",
+
+            // 2xx Success
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            203 => "Non-Authoritative Information",
+            204 => "No Content",
+            205 => "Reset Content",
+            206 => "Partial Content",
+
+            // 3xx Redirection
+            300 => "Multiple Choices",
+            301 => "Moved Permanently",
+            302 => "Found",
+            303 => "See Other",
+            304 => "Not Modified",
+            305 => "Use Proxy",
+            306 => "(Unused)",
+            307 => "Temporary Redirect",
+            308 => "Permanent Redirect",
+
+            // 4xx Client Error
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            402 => "Payment Required",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            406 => "Not Acceptable",
+            407 => "Proxy Authentication Required",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            410 => "Gone",
+            411 => "Length Required",
+            412 => "Precondition Failed",
+            413 => "Content Too Large",
+            414 => "URI Too Long",
+            415 => "Unsupported Media Type",
+            416 => "Range Not Satisfiable",
+            417 => "Expectation Failed",
+            418 => "I'm a tea pot",
+            421 => "Misdirected Request",
+            422 => "Unprocessable Content",
+            426 => "Upgrade Required",
+            428 => "Precondition Required",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+
+            // 5xx Server Error
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            505 => "HTTP Version Not Supported",
+
+            // Default
+            _ => "Unknown Status Code",
+        }
+    }
+}
+
+impl Default for StatusCode {
+    fn default() -> Self {
+        Self::OK
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl PartialEq<u32> for StatusCode {
+    fn eq(&self, other: &u32) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<StatusCode> for u32 {
+    fn eq(&self, other: &StatusCode) -> bool {
+        *self == other.0
+    }
+}
+
+impl From<StatusCode> for u32 {
+    fn from(status: StatusCode) -> Self {
+        status.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_classify_status_codes() {
+        assert!(StatusCode::EARLY_HINTS.is_informational());
+        assert!(StatusCode::CREATED.is_success());
+        assert!(StatusCode::SEE_OTHER.is_redirection());
+        assert!(StatusCode::GONE.is_client_error());
+        assert!(StatusCode::from_u32(599).unwrap().is_server_error());
+        assert!(!StatusCode::from_u32(600).unwrap().is_server_error());
+        assert!(!StatusCode::NOT_MODIFIED.allows_body());
+        assert!(StatusCode::from_u32(99).is_none());
+    }
+
+    #[test]
+    fn it_should_compare_with_numbers() {
+        assert_eq!(StatusCode::NOT_FOUND, 404);
+        assert_eq!(StatusCode::from_u32(404), Some(StatusCode::NOT_FOUND));
+        assert_eq!(
+            StatusCode::from_u32(799).unwrap().reason_phrase(),
+            "Unknown Status Code"
+        );
+        assert_eq!(StatusCode::OK.to_string(), "200");
+    }
+}
@@ -0,0 +1,207 @@
+//! Thread pool module
+//!
+//! A fixed number of threads pull accepted connections out of a bounded queue and serve
+//! each of them with the function given to [ThreadPool::new]. When the queue is full, the
+//! [OverflowPolicy] decides if the accept loop waits for a free slot or if the connection is
+//! answered with a `503 Service Unavailable` and closed.
+//!
+//! The pool is not tied to connections: the event loop uses it to run handlers, each job
+//! being a parsed request.
+use crate::content_type::ContentType;
+use crate::response::Response;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_POOL_SIZE: usize = 32;
+pub const DEFAULT_QUEUE_SIZE: usize = 256;
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What to do with a new connection when every thread is busy and the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    /// Stop accepting until a slot is free in the queue.
+    Block,
+    /// Answer `503 Service Unavailable` and close the connection.
+    Reject,
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub size: usize,
+    pub queue_size: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_POOL_SIZE,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            overflow: OverflowPolicy::Block,
+        }
+    }
+}
+
+pub struct ThreadPool<J: Send + 'static = TcpStream> {
+    sender: Option<SyncSender<J>>,
+    threads: Vec<JoinHandle<()>>,
+    overflow: OverflowPolicy,
+}
+
+impl<J: Send + 'static> ThreadPool<J> {
+    pub fn new<F>(config: &PoolConfig, serve: F) -> Self
+    where
+        F: Fn(J) + Send + Sync + 'static,
+    {
+        let (sender, receiver) = sync_channel(config.queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let serve: Arc<dyn Fn(J) + Send + Sync> = Arc::new(serve);
+        let threads = (0..config.size.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let serve = Arc::clone(&serve);
+                thread::spawn(move || run_thread(receiver, serve))
+            })
+            .collect();
+        Self {
+            sender: Some(sender),
+            threads,
+            overflow: config.overflow,
+        }
+    }
+
+    /// Queue a job without waiting. The job is given back when the queue is full or when
+    /// the pool is closed.
+    pub fn try_dispatch(&self, job: J) -> Result<(), J> {
+        let Some(sender) = self.sender.as_ref() else {
+            return Err(job);
+        };
+        sender.try_send(job).map_err(|e| match e {
+            TrySendError::Full(job) | TrySendError::Disconnected(job) => job,
+        })
+    }
+
+    pub fn overflow(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    /// Stop accepting jobs and wait for the threads to finish their connections. Return
+    /// false if some threads were still running when the timeout expired; they are then
+    /// detached.
+    pub fn join(mut self, timeout: Duration) -> bool {
+        drop(self.sender.take());
+        let deadline = Instant::now() + timeout;
+        while self.threads.iter().any(|thread| !thread.is_finished()) {
+            if Instant::now() >= deadline {
+                self.threads.clear();
+                return false;
+            }
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        true
+    }
+}
+
+impl ThreadPool<TcpStream> {
+    /// Queue a connection for the next available thread, applying the overflow policy
+    /// when the queue is full.
+    pub fn dispatch(&self, stream: TcpStream) {
+        let Some(sender) = self.sender.as_ref() else {
+            return;
+        };
+        match self.overflow {
+            OverflowPolicy::Block => {
+                if let Err(e) = sender.send(stream) {
+                    eprintln!("Error while dispatching connection: {e}");
+                }
+            }
+            OverflowPolicy::Reject => match sender.try_send(stream) {
+                Ok(()) => {}
+                Err(TrySendError::Full(stream)) => reject(stream),
+                Err(TrySendError::Disconnected(_)) => {
+                    eprintln!("Error while dispatching connection: pool is closed")
+                }
+            },
+        }
+    }
+}
+
+impl<J: Send + 'static> Drop for ThreadPool<J> {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run_thread<J>(receiver: Arc<Mutex<Receiver<J>>>, serve: Arc<dyn Fn(J) + Send + Sync>) {
+    loop {
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        let Ok(job) = job else {
+            return;
+        };
+        serve(job);
+    }
+}
+
+fn reject(mut stream: TcpStream) {
+    let mut response = Response::new(503, vec![], vec![], ContentType::TextHtml);
+    response.close_connection();
+    if let Err(e) = stream.write_all(&response.as_bytes()) {
+        eprintln!("Error while rejecting connection: {e}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::request::Request;
+    use crate::worker::Worker;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    fn handle_client_mock(_: Request) -> Response {
+        Response::new(200, vec![], vec![], ContentType::TextHtml)
+    }
+
+    #[test]
+    fn it_should_reject_when_queue_is_full() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = PoolConfig {
+            size: 1,
+            queue_size: 1,
+            overflow: OverflowPolicy::Reject,
+        };
+        let pool = ThreadPool::new(&config, |stream| {
+            Worker::new(stream, String::new()).run(&handle_client_mock)
+        });
+
+        let busy = TcpStream::connect(addr).unwrap();
+        pool.dispatch(listener.accept().unwrap().0);
+        thread::sleep(Duration::from_millis(50));
+        let queued = TcpStream::connect(addr).unwrap();
+        pool.dispatch(listener.accept().unwrap().0);
+        let mut rejected = TcpStream::connect(addr).unwrap();
+        pool.dispatch(listener.accept().unwrap().0);
+
+        let mut response = String::new();
+        rejected.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+        drop(busy);
+        drop(queued);
+    }
+}
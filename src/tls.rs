@@ -0,0 +1,201 @@
+//! TLS module, enabled with the `tls` feature
+//!
+//! A [TlsConfig] holds the certificates served by an [HttpServer](crate::http_server::HttpServer)
+//! once `set_tls` is called. Certificate chains and private keys are loaded from PEM files.
+//! Additional certificates can be registered for specific host names; they are selected
+//! with the SNI extension sent by the client, the default certificate being used otherwise.
+//!
+//! # Example
+//! ```rust,no_run
+//! use webserv_rs::content_type::ContentType;
+//! use webserv_rs::http_server::HttpServer;
+//! use webserv_rs::request::Request;
+//! use webserv_rs::response::Response;
+//! use webserv_rs::tls::TlsConfig;
+//!
+//! fn handle_client(_: Request) -> Response {
+//!     Response::new(200, vec![], vec![], ContentType::Text)
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     let mut tls = TlsConfig::from_pem_files("cert.pem", "key.pem")?;
+//!     tls.add_host("api.example.com", "api-cert.pem", "api-key.pem")?;
+//!
+//!     let mut server = HttpServer::new("0.0.0.0", 8443)?;
+//!     server.set_tls(tls);
+//!     server.run(handle_client)
+//! }
+//! ```
+use crate::socket::Socket;
+use rustls::crypto::ring::sign::any_supported_type;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+#[derive(Debug)]
+struct CertificateResolver {
+    default: Arc<CertifiedKey>,
+    hosts: HashMap<String, Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let key = client_hello
+            .server_name()
+            .and_then(|name| self.hosts.get(&name.to_lowercase()))
+            .unwrap_or(&self.default);
+        Some(Arc::clone(key))
+    }
+}
+
+pub struct TlsConfig {
+    resolver: CertificateResolver,
+}
+
+impl TlsConfig {
+    /// Load the default certificate chain and its private key.
+    pub fn from_pem_files<P: AsRef<Path>>(cert_path: P, key_path: P) -> std::io::Result<Self> {
+        Ok(Self {
+            resolver: CertificateResolver {
+                default: load_certified_key(cert_path.as_ref(), key_path.as_ref())?,
+                hosts: HashMap::new(),
+            },
+        })
+    }
+
+    /// Serve another certificate chain when the client asks for `hostname` through SNI.
+    pub fn add_host<P: AsRef<Path>>(
+        &mut self,
+        hostname: &str,
+        cert_path: P,
+        key_path: P,
+    ) -> std::io::Result<()> {
+        let key = load_certified_key(cert_path.as_ref(), key_path.as_ref())?;
+        self.resolver.hosts.insert(hostname.to_lowercase(), key);
+        Ok(())
+    }
+
+    pub(crate) fn server_config(&self) -> Arc<ServerConfig> {
+        let resolver = CertificateResolver {
+            default: Arc::clone(&self.resolver.default),
+            hosts: self.resolver.hosts.clone(),
+        };
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(resolver));
+        Arc::new(config)
+    }
+}
+
+/// Start a TLS session on an accepted connection. The handshake is done during the first
+/// read or write of the worker.
+pub(crate) fn accept(config: Arc<ServerConfig>, stream: TcpStream) -> std::io::Result<TlsStream> {
+    let connection =
+        ServerConnection::new(config).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(StreamOwned::new(connection, stream))
+}
+
+impl Socket for TlsStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        self.sock.set_write_timeout(timeout)
+    }
+}
+
+fn load_certified_key(cert_path: &Path, key_path: &Path) -> std::io::Result<Arc<CertifiedKey>> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    if certs.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("no certificate found in {}", cert_path.display()),
+        ));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let key = any_supported_type(&key).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(Arc::new(CertifiedKey::new(certs, key)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::content_type::ContentType;
+    use crate::http_server::HttpServer;
+    use crate::request::Request;
+    use crate::response::Response;
+    use rustls::pki_types::ServerName;
+    use rustls::{ClientConfig, ClientConnection, RootCertStore};
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
+    use std::thread;
+
+    fn handle_client_mock(_: Request) -> Response {
+        Response::new(200, b"Hello".to_vec(), vec![], ContentType::Text)
+    }
+
+    // Generate a self-signed certificate and return the PEM file paths and the certificate.
+    fn generate_certificate(hostname: &str) -> (PathBuf, PathBuf, CertificateDer<'static>) {
+        let certified = rcgen::generate_simple_self_signed(vec![hostname.to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!("webserv-rs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join(format!("{hostname}.cert.pem"));
+        let key_path = dir.join(format!("{hostname}.key.pem"));
+        std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+        (cert_path, key_path, certified.cert.der().clone())
+    }
+
+    fn request(addr: std::net::SocketAddr, hostname: &str, root: CertificateDer) -> String {
+        let mut roots = RootCertStore::empty();
+        roots.add(root).unwrap();
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = ServerName::try_from(hostname.to_string()).unwrap();
+        let connection = ClientConnection::new(Arc::new(config), name).unwrap();
+        let mut stream = StreamOwned::new(connection, TcpStream::connect(addr).unwrap());
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response);
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    #[test]
+    fn it_should_select_certificate_with_sni() {
+        let (cert_path, key_path, _) = generate_certificate("default.test");
+        let (api_cert_path, api_key_path, api_cert) = generate_certificate("api.test");
+        let mut tls = TlsConfig::from_pem_files(&cert_path, &key_path).unwrap();
+        tls.add_host("api.test", &api_cert_path, &api_key_path)
+            .unwrap();
+
+        let mut server = HttpServer::new("127.0.0.1", 0).unwrap();
+        server.set_tls(tls);
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle();
+        let server = thread::spawn(move || server.run(handle_client_mock));
+
+        let response = request(addr, "api.test", api_cert);
+
+        handle.shutdown();
+        server.join().unwrap().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("Hello"));
+    }
+}
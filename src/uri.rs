@@ -0,0 +1,257 @@
+//! Request target module
+//!
+//! A [Uri] is the parsed request-target of a [Request](crate::request::Request), available in
+//! its `target` field. The four forms of RFC 9112 section 3.2 are accepted:
+//! * origin-form: `/users/42?verbose=1`
+//! * absolute-form: `http://example.com/users/42`, used by clients talking to a proxy
+//! * authority-form: `example.com:443`, used by `CONNECT`
+//! * asterisk-form: `*`, used by `OPTIONS`
+//!
+//! The path is percent-decoded and its dot-segments are removed, so that `/a/./b/../%63`
+//! becomes `/a/c`. An encoded slash or percent sign stays encoded (`%2F` and `%25`), so that
+//! `/users/a%2Fb` keeps two segments; the [Router](crate::router::Router) decodes them in the
+//! captured parameters. An encoded NUL is a `400 Bad Request`. The query is kept raw and is
+//! also decoded into a list of parameters, a `+` standing for a space. A key given several
+//! times keeps all its values.
+//!
+//! # Example
+//! ```rust
+//! use webserv_rs::uri::Uri;
+//!
+//! let uri = Uri::parse("/search/../find%20me?tag=a&tag=b+c").unwrap();
+//! assert_eq!(uri.path(), "/find me");
+//! assert_eq!(uri.query(), Some("tag=a&tag=b+c"));
+//! assert_eq!(uri.get_query_all("tag").collect::<Vec<_>>(), ["a", "b c"]);
+//! ```
+use crate::http_error::HttpError;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Uri {
+    scheme: Option<String>,
+    authority: Option<String>,
+    path: String,
+    query: Option<String>,
+    query_params: Vec<(String, String)>,
+}
+
+impl Uri {
+    pub fn parse(target: &str) -> Result<Self, HttpError> {
+        if target.contains('#') {
+            return Err(HttpError::Error400);
+        }
+        if target == "*" {
+            return Ok(Self {
+                path: target.to_string(),
+                ..Self::default()
+            });
+        }
+        let (scheme, authority, rest) = if target.starts_with('/') {
+            (None, None, target)
+        } else if let Some((scheme, rest)) = target.split_once("://") {
+            let index = rest.find(['/', '?']).unwrap_or(rest.len());
+            let (authority, rest) = rest.split_at(index);
+            (Some(scheme), Some(authority), rest)
+        } else {
+            (None, Some(target), "")
+        };
+        if scheme.is_some_and(|scheme| !is_scheme(scheme)) || authority == Some("") {
+            return Err(HttpError::Error400);
+        }
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+        let path = match (path, authority) {
+            ("", Some(_)) if scheme.is_none() => String::new(),
+            ("", _) => "/".to_string(),
+            (path, _) => remove_dot_segments(&decode_path(path)?),
+        };
+        Ok(Self {
+            scheme: scheme.map(str::to_ascii_lowercase),
+            authority: authority.map(str::to_string),
+            path,
+            query: query.map(str::to_string),
+            query_params: query.map(parse_query).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    /// Decoded and normalized path, with `%2F` and `%25` left encoded. Empty for an
+    /// authority-form target, `*` for the asterisk-form.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Raw query, without the `?`.
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// Scheme of an absolute-form target, in lowercase.
+    pub fn scheme(&self) -> Option<&str> {
+        self.scheme.as_deref()
+    }
+
+    /// Host and port of an absolute-form or authority-form target.
+    pub fn authority(&self) -> Option<&str> {
+        self.authority.as_deref()
+    }
+
+    /// Decoded query parameters, in order.
+    pub fn query_params(&self) -> &[(String, String)] {
+        &self.query_params
+    }
+
+    /// First value of a query parameter.
+    pub fn get_query(&self, key: &str) -> Option<&str> {
+        self.query_params
+            .iter()
+            .find(|(param_key, _)| param_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of a query parameter.
+    pub fn get_query_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.query_params
+            .iter()
+            .filter(move |(param_key, _)| param_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+pub(crate) fn parse_query(query: &str) -> Result<Vec<(String, String)>, HttpError> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key, true)?, percent_decode(value, true)?))
+        })
+        .collect()
+}
+
+/// Decode the `%XX` sequences of a URI component, and `+` as a space when `plus_as_space`
+/// is set. Invalid sequences and decoded bytes that are not UTF-8 are a `400 Bad Request`.
+pub fn percent_decode(value: &str, plus_as_space: bool) -> Result<String, HttpError> {
+    decode(value, plus_as_space, &[])
+}
+
+// Decode a path, keeping `/` and `%` encoded so that decoding does not change its segments
+// and the segments can be decoded once more. NUL is rejected.
+fn decode_path(path: &str) -> Result<String, HttpError> {
+    let path = decode(path, false, b"/%")?;
+    if path.contains('\0') {
+        return Err(HttpError::Error400);
+    }
+    Ok(path)
+}
+
+// Decode the `%XX` sequences, except the ones giving a byte of `keep`, which are written
+// back in uppercase.
+fn decode(value: &str, plus_as_space: bool, keep: &[u8]) -> Result<String, HttpError> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = bytes.get(i + 1..i + 3).ok_or(HttpError::Error400)?;
+                // `from_str_radix` would accept a sign, as in `%+f`.
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return Err(HttpError::Error400);
+                }
+                let hex = std::str::from_utf8(hex).map_err(|_| HttpError::Error400)?;
+                let byte = u8::from_str_radix(hex, 16).map_err(|_| HttpError::Error400)?;
+                match keep.contains(&byte) {
+                    true => decoded.extend_from_slice(format!("%{byte:02X}").as_bytes()),
+                    false => decoded.push(byte),
+                }
+                i += 3;
+                continue;
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).map_err(|_| HttpError::Error400)
+}
+
+// Resolve the `.` and `..` segments of an absolute path, following RFC 3986 section 5.2.4.
+// A `..` at the root stays at the root.
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = path.split('/').skip(1).peekable();
+    while let Some(segment) = parts.next() {
+        let last = parts.peek().is_none();
+        match segment {
+            "." | ".." => {
+                if segment == ".." {
+                    segments.pop();
+                }
+                if last {
+                    segments.push("");
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_should_normalize_path() {
+        let uri = Uri::parse("/a/./b/../../../c%2e%2e/%63/").unwrap();
+
+        assert_eq!(uri.path(), "/c../c/");
+        assert_eq!(Uri::parse("/a/b/..").unwrap().path(), "/a/");
+        assert_eq!(Uri::parse("/%2e%2e/etc").unwrap().path(), "/etc");
+    }
+
+    #[test]
+    fn it_should_keep_encoded_slash_and_reject_nul() {
+        assert_eq!(
+            Uri::parse("/a%2fb/%2E/c/%2E%2E/%25").unwrap().path(),
+            "/a%2Fb/%25"
+        );
+        assert_eq!(Uri::parse("/a%2F..%2Fb").unwrap().path(), "/a%2F..%2Fb");
+        assert!(Uri::parse("/file%00.txt").is_err());
+    }
+
+    #[test]
+    fn it_should_parse_query_parameters() {
+        let uri = Uri::parse("/?a=1&b=x%26y&a=2&flag&next=http://host/").unwrap();
+
+        assert_eq!(uri.get_query("b"), Some("x&y"));
+        assert_eq!(uri.get_query_all("a").collect::<Vec<_>>(), ["1", "2"]);
+        assert_eq!(uri.get_query("flag"), Some(""));
+        assert_eq!(uri.get_query("next"), Some("http://host/"));
+        assert!(Uri::parse("/?a=%zz").is_err());
+        assert!(Uri::parse("/%+f").is_err());
+        assert!(Uri::parse("/?a=%-1").is_err());
+    }
+
+    #[test]
+    fn it_should_parse_absolute_and_authority_forms() {
+        let uri = Uri::parse("HTTP://example.com:8080/path?q=1").unwrap();
+        assert_eq!(uri.scheme(), Some("http"));
+        assert_eq!(uri.authority(), Some("example.com:8080"));
+        assert_eq!(uri.path(), "/path");
+        assert_eq!(uri.get_query("q"), Some("1"));
+
+        assert_eq!(Uri::parse("http://example.com").unwrap().path(), "/");
+        let uri = Uri::parse("example.com:443").unwrap();
+        assert_eq!(uri.authority(), Some("example.com:443"));
+        assert_eq!(uri.path(), "");
+    }
+}
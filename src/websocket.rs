@@ -0,0 +1,434 @@
+//! WebSocket module, following [RFC 6455](https://datatracker.ietf.org/doc/html/rfc6455)
+//!
+//! [upgrade] validates the opening handshake of a request and returns the
+//! `101 Switching Protocols` response. Once it is sent, the worker gives the connection to
+//! the WebSocket handler as a [WebSocket] used to exchange text and binary [Message].
+//!
+//! The [WebSocket] answers pings, reassembles fragmented messages and handles the closing
+//! handshake. Protocol errors from the client close the connection with the matching status
+//! code, as does a close frame with a code that cannot be sent on the wire, such as `1005`,
+//! or with a reason that is not UTF-8.
+//!
+//! WebSocket handlers are usually registered on a [Router](crate::router::Router) with
+//! `Router::websocket`.
+//!
+//! # Example
+//! ```rust
+//! use webserv_rs::request::Request;
+//! use webserv_rs::router::Router;
+//! use webserv_rs::websocket::WebSocket;
+//!
+//! fn echo(mut websocket: WebSocket, _: Request) {
+//!     while let Ok(Some(message)) = websocket.recv() {
+//!         if websocket.send(message).is_err() {
+//!             break;
+//!         }
+//!     }
+//! }
+//!
+//! let mut router = Router::new();
+//! router.websocket("/echo", echo);
+//! ```
+use crate::content_type::ContentType;
+use crate::header_map::has_token;
+use crate::http_error::error_response;
+use crate::request::{Method, Request};
+use crate::response::Response;
+use crate::socket::Socket;
+use base64::prelude::*;
+use sha1::{Digest, Sha1};
+use std::io::{Error, ErrorKind};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024 * 16;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_PAYLOAD: u16 = 1007;
+pub const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+pub struct WebSocket<'a> {
+    socket: &'a mut dyn Socket,
+    buffer: Vec<u8>,
+    closed: bool,
+}
+
+impl<'a> WebSocket<'a> {
+    pub fn new(socket: &'a mut dyn Socket, leftover: Vec<u8>) -> Self {
+        Self {
+            socket,
+            buffer: leftover,
+            closed: false,
+        }
+    }
+
+    /// Wait for the next message. Return `None` once the connection is closed.
+    pub fn recv(&mut self) -> std::io::Result<Option<Message>> {
+        let mut fragments: Option<(u8, Vec<u8>)> = None;
+        loop {
+            let Some(frame) = self.read_frame()? else {
+                return Ok(None);
+            };
+            match frame.opcode {
+                OPCODE_PING => self.write_frame(OPCODE_PONG, &frame.payload)?,
+                OPCODE_PONG => {}
+                OPCODE_CLOSE => {
+                    if frame.payload.len() == 1 {
+                        return Err(self.fail(CLOSE_PROTOCOL_ERROR, "invalid close payload"));
+                    }
+                    if let [high, low, reason @ ..] = frame.payload.as_slice() {
+                        if !is_valid_close_code(u16::from_be_bytes([*high, *low])) {
+                            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "invalid close code"));
+                        }
+                        if std::str::from_utf8(reason).is_err() {
+                            return Err(
+                                self.fail(CLOSE_INVALID_PAYLOAD, "close reason is not UTF-8")
+                            );
+                        }
+                    }
+                    if !self.closed {
+                        self.closed = true;
+                        let code = frame.payload.get(..2).unwrap_or_default();
+                        self.write_frame(OPCODE_CLOSE, code)?;
+                    }
+                    return Ok(None);
+                }
+                OPCODE_TEXT | OPCODE_BINARY => {
+                    if fragments.is_some() {
+                        return Err(self.fail(CLOSE_PROTOCOL_ERROR, "expected continuation"));
+                    }
+                    if frame.fin {
+                        return self.make_message(frame.opcode, frame.payload).map(Some);
+                    }
+                    fragments = Some((frame.opcode, frame.payload));
+                }
+                OPCODE_CONTINUATION => {
+                    let Some((opcode, mut payload)) = fragments.take() else {
+                        return Err(self.fail(CLOSE_PROTOCOL_ERROR, "unexpected continuation"));
+                    };
+                    if payload.len() + frame.payload.len() > MAX_MESSAGE_SIZE {
+                        return Err(self.fail(CLOSE_MESSAGE_TOO_BIG, "message too big"));
+                    }
+                    payload.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        return self.make_message(opcode, payload).map(Some);
+                    }
+                    fragments = Some((opcode, payload));
+                }
+                _ => return Err(self.fail(CLOSE_PROTOCOL_ERROR, "unknown opcode")),
+            }
+        }
+    }
+
+    pub fn send(&mut self, message: Message) -> std::io::Result<()> {
+        match message {
+            Message::Text(text) => self.send_text(&text),
+            Message::Binary(data) => self.send_binary(&data),
+        }
+    }
+
+    pub fn send_text(&mut self, text: &str) -> std::io::Result<()> {
+        self.send_data(OPCODE_TEXT, text.as_bytes())
+    }
+
+    pub fn send_binary(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.send_data(OPCODE_BINARY, data)
+    }
+
+    pub fn ping(&mut self, payload: &[u8]) -> std::io::Result<()> {
+        if payload.len() > 125 {
+            return Err(Error::new(ErrorKind::InvalidInput, "ping payload too long"));
+        }
+        self.send_data(OPCODE_PING, payload)
+    }
+
+    /// Start the closing handshake. `recv` returns `None` once the client answers.
+    pub fn close(&mut self, code: u16, reason: &str) -> std::io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        payload.truncate(125);
+        self.write_frame(OPCODE_CLOSE, &payload)
+    }
+
+    fn send_data(&mut self, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+        if self.closed {
+            return Err(Error::new(ErrorKind::NotConnected, "WebSocket is closed"));
+        }
+        self.write_frame(opcode, payload)
+    }
+
+    fn make_message(&mut self, opcode: u8, payload: Vec<u8>) -> std::io::Result<Message> {
+        if opcode == OPCODE_BINARY {
+            return Ok(Message::Binary(payload));
+        }
+        match String::from_utf8(payload) {
+            Ok(text) => Ok(Message::Text(text)),
+            Err(_) => Err(self.fail(CLOSE_INVALID_PAYLOAD, "text message is not UTF-8")),
+        }
+    }
+
+    // Close the connection after a protocol error and return the error for the caller.
+    fn fail(&mut self, code: u16, reason: &str) -> Error {
+        let _ = self.close(code, reason);
+        Error::new(ErrorKind::InvalidData, reason.to_string())
+    }
+
+    fn read_frame(&mut self) -> std::io::Result<Option<Frame>> {
+        if !self.fill(2)? {
+            return Ok(None);
+        }
+        let fin = self.buffer[0] & 0x80 != 0;
+        let reserved = self.buffer[0] & 0x70;
+        let opcode = self.buffer[0] & 0x0F;
+        let masked = self.buffer[1] & 0x80 != 0;
+        if reserved != 0 {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "reserved bits set"));
+        }
+        if !masked {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "client frame not masked"));
+        }
+        let (length, mut offset) = match self.buffer[1] & 0x7F {
+            126 => {
+                self.fill(4)?;
+                (
+                    u16::from_be_bytes([self.buffer[2], self.buffer[3]]) as u64,
+                    4,
+                )
+            }
+            127 => {
+                self.fill(10)?;
+                let mut bytes = [0u8; 8];
+                bytes.copy_from_slice(&self.buffer[2..10]);
+                (u64::from_be_bytes(bytes), 10)
+            }
+            length => (length as u64, 2),
+        };
+        if opcode >= OPCODE_CLOSE && (!fin || length > 125) {
+            return Err(self.fail(CLOSE_PROTOCOL_ERROR, "invalid control frame"));
+        }
+        if length > MAX_MESSAGE_SIZE as u64 {
+            return Err(self.fail(CLOSE_MESSAGE_TOO_BIG, "message too big"));
+        }
+        let length = length as usize;
+        self.fill(offset + 4 + length)?;
+        let mut mask = [0u8; 4];
+        mask.copy_from_slice(&self.buffer[offset..offset + 4]);
+        offset += 4;
+        let payload = self.buffer[offset..offset + length]
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect();
+        self.buffer.drain(..offset + length);
+        Ok(Some(Frame {
+            fin,
+            opcode,
+            payload,
+        }))
+    }
+
+    // Read until the buffer holds `size` bytes. Return false if the connection was closed
+    // between two frames.
+    fn fill(&mut self, size: usize) -> std::io::Result<bool> {
+        while self.buffer.len() < size {
+            let mut tmp = [0u8; 4096];
+            let n = self.socket.read(&mut tmp)?;
+            if n == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(false);
+                }
+                return Err(Error::new(ErrorKind::UnexpectedEof, "incomplete frame"));
+            }
+            self.buffer.extend_from_slice(&tmp[..n]);
+        }
+        Ok(true)
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+        let mut frame = vec![0x80 | opcode];
+        if payload.len() < 126 {
+            frame.push(payload.len() as u8);
+        } else if payload.len() <= u16::MAX as usize {
+            frame.push(126);
+            frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        } else {
+            frame.push(127);
+            frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+        }
+        frame.extend_from_slice(payload);
+        self.socket.write_all(&frame)?;
+        self.socket.flush()
+    }
+}
+
+/// Validate the opening handshake and return the response switching the connection to the
+/// WebSocket protocol. The handler is called with the [WebSocket] once the response is sent.
+pub fn upgrade<F>(request: Request, handler: F) -> Response
+where
+    F: FnOnce(WebSocket, Request) + Send + 'static,
+{
+    let upgrade = request.get_value("Upgrade").unwrap_or_default();
+    let connection = request.get_value("Connection").unwrap_or_default();
+    if !has_token(upgrade, "websocket") || !has_token(connection, "upgrade") {
+        let mut response = error_response(426);
+        response.set_header("Upgrade", "websocket");
+        return response;
+    }
+    if request.get_value("Sec-WebSocket-Version") != Some("13") {
+        let mut response = error_response(426);
+        response.set_header("Sec-WebSocket-Version", "13");
+        return response;
+    }
+    let Some(key) = request.get_value("Sec-WebSocket-Key") else {
+        return error_response(400);
+    };
+    if request.method != Method::Get || BASE64_STANDARD.decode(key).map(|key| key.len()) != Ok(16) {
+        return error_response(400);
+    }
+    let headers = vec![
+        ("Upgrade".to_string(), "websocket".to_string()),
+        ("Connection".to_string(), "Upgrade".to_string()),
+        ("Sec-WebSocket-Accept".to_string(), accept_key(key)),
+    ];
+    let mut response = Response::new(101, vec![], headers, ContentType::Text);
+    response.headers.remove("Content-length");
+    response.headers.remove("Content-Type");
+    response.upgrade = Some(Box::new(move |socket, leftover| {
+        handler(WebSocket::new(socket, leftover), request)
+    }));
+    response
+}
+
+fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(GUID.as_bytes());
+    BASE64_STANDARD.encode(hasher.finalize())
+}
+
+// Status codes a close frame can carry, RFC 6455 section 7.4: the defined codes except the
+// ones reserved for local use (1004, 1005, 1006 and 1015), and the codes of the
+// applications.
+fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::TcpStreamMock;
+
+    const HANDSHAKE: &str = "GET /echo HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13";
+
+    fn masked_frame(first: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [1u8, 2, 3, 4];
+        let mut frame = vec![first, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    fn echo(mut websocket: WebSocket, _: Request) {
+        while let Ok(Some(message)) = websocket.recv() {
+            websocket.send(message).unwrap();
+        }
+    }
+
+    // Run the echo handler on the given frames and return what the server sent.
+    fn run_echo(frames: &[Vec<u8>]) -> Vec<u8> {
+        let data = frames.iter().map(|d| d.as_slice()).collect::<Vec<&[u8]>>();
+        let mut socket = TcpStreamMock::new(&data);
+        echo(
+            WebSocket::new(&mut socket, vec![]),
+            Request::parse(HANDSHAKE).unwrap(),
+        );
+        socket.receive
+    }
+
+    #[test]
+    fn it_should_accept_handshake() {
+        let response = upgrade(Request::parse(HANDSHAKE).unwrap(), echo);
+
+        assert_eq!(response.status, 101);
+        assert_eq!(
+            response.get_value("Sec-WebSocket-Accept"),
+            Some("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=")
+        );
+        assert!(response.get_value("Content-length").is_none());
+        assert!(response.upgrade.is_some());
+    }
+
+    #[test]
+    fn it_should_ask_for_upgrade() {
+        let request = Request::parse("GET /echo HTTP/1.1\r\nHost: localhost").unwrap();
+        let response = upgrade(request, echo);
+
+        assert_eq!(response.status, 426);
+        assert_eq!(response.get_value("Upgrade"), Some("websocket"));
+    }
+
+    #[test]
+    fn it_should_echo_messages_and_close() {
+        let frames = run_echo(&[
+            masked_frame(0x81, b"Hello"),
+            masked_frame(0x88, &CLOSE_NORMAL.to_be_bytes()),
+        ]);
+
+        assert_eq!(frames, b"\x81\x05Hello\x88\x02\x03\xe8");
+    }
+
+    #[test]
+    fn it_should_reassemble_fragments_and_answer_ping() {
+        let frames = run_echo(&[
+            masked_frame(0x01, b"Hel"),
+            masked_frame(0x89, b"p"),
+            masked_frame(0x80, b"lo"),
+        ]);
+
+        assert_eq!(frames, b"\x8a\x01p\x81\x05Hello");
+    }
+
+    #[test]
+    fn it_should_reject_reserved_close_codes() {
+        for code in [999u16, 1004, 1005, 1006, 1015, 2000, 5000] {
+            let frames = run_echo(&[masked_frame(0x88, &code.to_be_bytes())]);
+            assert_eq!(frames, b"\x88\x14\x03\xeainvalid close code", "{code}");
+        }
+
+        let frames = run_echo(&[masked_frame(0x88, b"\x03\xe8\xff")]);
+        assert_eq!(&frames[2..4], b"\x03\xef");
+        let frames = run_echo(&[masked_frame(0x88, b"\x0b\xb8bye")]);
+        assert_eq!(frames, b"\x88\x02\x0b\xb8");
+    }
+
+    #[test]
+    fn it_should_close_on_unmasked_frame() {
+        let frames = run_echo(&[b"\x81\x02Hi".to_vec()]);
+
+        assert_eq!(frames[0], 0x88);
+        assert_eq!(&frames[2..4], b"\x03\xea");
+    }
+}
@@ -1,225 +1,378 @@
-use crate::chunk_handler::ChunkHandler;
-use crate::encoding::{uncompress, Encoding};
-use crate::http_error::{handle_error, HttpError};
-use crate::request::Request;
-use crate::response::Response;
+use crate::body::{Body, BodyState, LimitedBody, RawBody, SharedSocket, MAX_DRAIN_SIZE};
+use crate::config::Limits;
+use crate::encoding::decoder;
+use crate::handler::Handler;
+use crate::http_error::{error_response, handle_error, HttpError};
+use crate::interim::{Interim, CONTINUE};
+use crate::parser::{get_body_codings, BodyFraming, RequestParser};
+use crate::request::{Request, Version};
+use crate::response::{Response, Upgrade};
+use crate::shutdown::Connection;
+use crate::socket::Socket;
 use std::error::Error;
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
-const MAX_HEADER_SIZE: usize = 16_000;
-pub const MAX_BODY_SIZE: usize = 1024 * 1024 * 10;
+/// Time limits applied by the worker on each connection. `None` disables the limit.
+///
+/// * `header`: time to receive the whole header block once the first byte arrived.
+/// * `body`: time to receive the whole body once the header block is parsed.
+/// * `idle`: time a keep-alive connection waits for the next request.
+/// * `write`: time allowed to each write of the response.
+///
+/// When the header or the body timeout expires, the worker answers `408 Request Timeout`.
+/// When the idle timeout expires, the connection is closed silently. With body streaming,
+/// the body timeout applies to each read of the body instead.
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    pub header: Option<Duration>,
+    pub body: Option<Duration>,
+    pub idle: Option<Duration>,
+    pub write: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            header: Some(Duration::from_secs(10)),
+            body: Some(Duration::from_secs(30)),
+            idle: Some(Duration::from_secs(5)),
+            write: Some(Duration::from_secs(30)),
+        }
+    }
+}
 
-pub struct Worker<T: Read + Write> {
-    socket: T,
-    leftover: Vec<u8>,
+pub struct Worker<T: Socket> {
+    socket: SharedSocket<T>,
+    parser: RequestParser,
     peer: String,
+    connection: Option<Connection>,
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+    max_requests: Option<usize>,
+    body_streaming: bool,
+    body: Option<Arc<Mutex<BodyState>>>,
 }
 
-impl<T: Read + Write> Worker<T> {
+impl<T: Socket + Send + 'static> Worker<T> {
     pub fn new(socket: T, peer: String) -> Self {
         Self {
-            socket,
-            leftover: vec![0u8; 0],
+            socket: SharedSocket::new(socket),
+            parser: RequestParser::new(),
             peer,
+            connection: None,
+            timeouts: Timeouts::default(),
+            deadline: None,
+            max_requests: None,
+            body_streaming: false,
+            body: None,
         }
     }
 
-    pub fn run(&mut self, handle_client: fn(Request) -> Response) {
-        loop {
-            if let Some(response) = self.get_response(handle_client) {
-                if let Err(e) = self.socket.write_all(&response.as_bytes()) {
-                    eprintln!("Error while writing in socket({}): {e}", self.peer);
-                    break;
-                }
-                if response.is_error_status() {
-                    break;
-                }
-            } else {
+    /// Give the request to the handler once its head is received, the body being read on
+    /// demand, see [crate::body].
+    pub fn with_body_streaming(mut self, body_streaming: bool) -> Self {
+        self.body_streaming = body_streaming;
+        self
+    }
+
+    /// Close the connection after the given number of requests.
+    pub fn with_max_requests(mut self, max_requests: Option<usize>) -> Self {
+        self.max_requests = max_requests;
+        self
+    }
+
+    /// Size limits of the requests, see [Limits].
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.parser = RequestParser::with_limits(limits);
+        self
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Attach the connection to a server shutdown handle so that the worker stops when the
+    /// server shuts down.
+    pub fn with_connection(mut self, connection: Connection) -> Self {
+        self.connection = Some(connection);
+        self
+    }
+
+    pub fn run<H: Handler + ?Sized>(&mut self, handler: &H) {
+        if let Err(e) = self.socket.set_write_timeout(self.timeouts.write) {
+            eprintln!("Error while setting write timeout({}): {e}", self.peer);
+            return;
+        }
+        let mut served = 0;
+        while self.wait_request() {
+            let Some((mut response, keep_alive, version)) = self.get_response(handler) else {
+                break;
+            };
+            if self.is_body_too_large() {
+                response = error_response(413);
+            }
+            let keep_alive = keep_alive && self.finish_body();
+            served += 1;
+            if let Some(upgrade) = response.upgrade.take() {
+                self.upgrade(&response, upgrade);
+                break;
+            }
+            let close_delimited = response.set_framing(version);
+            let keep_alive = keep_alive
+                && !close_delimited
+                && !response.is_close()
+                && !self.is_shutdown()
+                && self.max_requests.is_none_or(|max| served < max);
+            set_connection_headers(
+                &mut response,
+                keep_alive,
+                served,
+                &self.timeouts,
+                self.max_requests,
+            );
+            let written = response.write_to(&mut self.socket);
+            if let Err(e) = written.and_then(|_| self.socket.flush()) {
+                eprintln!("Error while writing in socket({}): {e}", self.peer);
+                break;
+            }
+            if !keep_alive {
                 break;
             }
         }
         println!("Connection end with : {}", self.peer);
     }
 
-    fn get_response(&mut self, handle_client: fn(Request) -> Response) -> Option<Response> {
+    // Send the switching protocols response and hand the connection over.
+    fn upgrade(&mut self, response: &Response, upgrade: Upgrade) {
+        let written = self.socket.write_all(&response.as_bytes());
+        if let Err(e) = written.and_then(|_| self.socket.flush()) {
+            eprintln!("Error while writing in socket({}): {e}", self.peer);
+            return;
+        }
+        if let Err(e) = self.socket.set_read_timeout(None) {
+            eprintln!("Error while upgrading connection({}): {e}", self.peer);
+            return;
+        }
+        let leftover = self.parser.take_buffer();
+        upgrade(&mut self.socket, leftover);
+    }
+
+    fn wait_request(&self) -> bool {
+        match self.connection.as_ref() {
+            Some(connection) => connection.wait_request(),
+            None => true,
+        }
+    }
+
+    fn start_request(&mut self) {
+        self.deadline = self.timeouts.header.map(|timeout| Instant::now() + timeout);
+        if let Some(connection) = self.connection.as_ref() {
+            connection.start_request();
+        }
+    }
+
+    fn is_shutdown(&self) -> bool {
+        match self.connection.as_ref() {
+            Some(connection) => connection.is_shutdown(),
+            None => false,
+        }
+    }
+
+    // Return the response, whether the client asked to keep the connection open and the
+    // version of the request. The connection is always closed after an error while reading
+    // the request.
+    fn get_response<H: Handler + ?Sized>(
+        &mut self,
+        handler: &H,
+    ) -> Option<(Response, bool, Version)> {
         match self.get_request() {
-            Ok(request) => {
-                if let Some(request) = request {
-                    return Some(handle_client(request));
-                } else {
-                    return None;
-                }
+            Ok(Some(request)) => {
+                let keep_alive = request.is_keep_alive();
+                let version = request.version;
+                Some((handler.handle(request), keep_alive, version))
             }
-            Err(error) => return Some(handle_error(error)),
+            Ok(None) => None,
+            Err(error) => Some((handle_error(error), false, Version::Http11)),
         }
     }
 
     fn get_request(&mut self) -> Result<Option<Request>, Box<dyn Error>> {
-        let mut buffer = vec![0u8; 0];
-        if self.leftover.len() > 0 {
-            buffer.extend_from_slice(&self.leftover);
+        if self.parser.is_empty() {
+            self.deadline = self.timeouts.idle.map(|timeout| Instant::now() + timeout);
+        } else {
+            self.start_request();
         }
+        let mut reading_body = false;
         loop {
+            if self.body_streaming {
+                if let Some((request, framing)) = self.parser.parse_head(None)? {
+                    return self.stream_body(request, framing).map(Some);
+                }
+            } else if let Some(mut request) = self.parser.parse()? {
+                request.interim = Some(Interim::new(self.socket.handle()));
+                return Ok(Some(request));
+            }
+            if self.parser.take_continue() {
+                self.socket.write_all(CONTINUE)?;
+                self.socket.flush()?;
+            }
+            if self.parser.is_reading_body() && !reading_body {
+                reading_body = true;
+                self.deadline = self.timeouts.body.map(|timeout| Instant::now() + timeout);
+            }
             let mut tmp = [0u8; 1024];
-            let n = self.socket.read(&mut tmp)?;
+            let n = match self.read_socket(&mut tmp) {
+                Err(e) if self.parser.is_empty() && is_timeout(e.as_ref()) => return Ok(None),
+                result => result?,
+            };
             if n == 0 {
+                if reading_body {
+                    return Err(Box::new(HttpError::Error400));
+                }
                 return Ok(None);
             }
-            buffer.extend_from_slice(&tmp[..n]);
-            if let Some(index) = get_double_crcn_index(&buffer) {
-                let request = self.process_packet(index, &buffer)?;
-                return Ok(Some(request));
-            }
-            if buffer.len() > MAX_HEADER_SIZE {
-                return Err(Box::new(HttpError::Error400));
+            if self.parser.is_empty() {
+                self.start_request();
             }
+            self.parser.feed(&tmp[..n]);
         }
     }
 
-    fn process_packet(&mut self, index: usize, buffer: &[u8]) -> Result<Request, Box<dyn Error>> {
-        let mut request = Request::new(&String::from_utf8_lossy(&buffer[..index]));
-        if request.is_body() {
-            let buffer = &buffer[index + 4..];
-            request.body = self.read_body(&buffer, &request)?;
-        } else {
-            self.leftover = buffer[index + 4..].to_vec();
+    // Attach to the request a reader pulling its body from the socket.
+    fn stream_body(
+        &mut self,
+        mut request: Request,
+        framing: Option<BodyFraming>,
+    ) -> Result<Request, Box<dyn Error>> {
+        request.interim = Some(Interim::new(self.socket.handle()));
+        let Some(framing) = framing else {
+            return Ok(request);
+        };
+        let encodings = get_body_codings(&request)?;
+        request.headers.remove("Content-Encoding");
+        self.socket.set_read_timeout(self.timeouts.body)?;
+        let expect_continue = request.expects_continue();
+        let state = BodyState::new(framing, self.parser.take_buffer(), expect_continue);
+        let mut reader: Box<dyn Read + Send> = Box::new(RawBody::new(&state, self.socket.handle()));
+        for encoding in encodings.iter() {
+            reader = decoder(reader, *encoding)?;
+        }
+        if !encodings.is_empty() {
+            let max_body_size = self.parser.limits().max_body_size;
+            reader = Box::new(LimitedBody::new(reader, max_body_size, &state));
         }
+        request.stream = Some(Body::stream(reader, &state));
+        self.body = Some(state);
         Ok(request)
     }
 
-    fn read_body(&mut self, buffer: &[u8], request: &Request) -> Result<Vec<u8>, Box<dyn Error>> {
-        let body = self.get_body(buffer, request)?;
-        self.uncompress(&body, &request)
+    fn is_body_too_large(&self) -> bool {
+        self.body.as_ref().is_some_and(|state| {
+            let state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            state.is_too_large()
+        })
     }
 
-    fn get_body(&mut self, buffer: &[u8], request: &Request) -> Result<Vec<u8>, Box<dyn Error>> {
-        if request.is_chunked() {
-            self.handle_chunked_body(buffer)
-        } else if let Some(body_length) = request.get_content_length() {
-            if body_length > MAX_BODY_SIZE {
-                return Err(Box::new(HttpError::Error413));
+    // Drain the body left unread by the handler and give the bytes following it back to the
+    // parser. Return false when the connection must be closed instead.
+    fn finish_body(&mut self) -> bool {
+        let Some(state) = self.body.take() else {
+            return true;
+        };
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut socket = self.socket.lock();
+        match state.drain(&mut *socket, MAX_DRAIN_SIZE) {
+            Some(leftover) => {
+                self.parser.feed(&leftover);
+                true
             }
-            self.handle_content_length_body(buffer, body_length)
-        } else {
-            Err(Box::new(HttpError::Error400))
+            None => false,
         }
     }
 
-    fn uncompress(&mut self, body: &[u8], request: &Request) -> Result<Vec<u8>, Box<dyn Error>> {
-        if let Some(encodings) = request.get_value("Transfer-Encoding") {
-            let mut body = body.to_vec();
-            for encoding in encodings.split(',').rev() {
-                if encoding.to_lowercase().trim() == "chunked" {
-                    continue;
+    // Read from the socket without going past the current deadline.
+    fn read_socket(&mut self, buffer: &mut [u8]) -> Result<usize, Box<dyn Error>> {
+        let timeout = match self.deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(Box::new(HttpError::Error408));
                 }
-                if let Some(encoding) = get_encoding(encoding) {
-                    body = uncompress(&body, encoding)?;
-                } else {
-                    return Err(Box::new(HttpError::Error400));
-                };
+                Some(remaining)
             }
-            Ok(body)
-        } else {
-            Ok(body.to_vec())
-        }
-    }
-
-    fn handle_chunked_body(&mut self, leftover: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut chunk_handler = ChunkHandler::new(leftover)?;
-        if !chunk_handler.is_body_ready() {
-            loop {
-                let mut tmp = [0u8; 1024];
-                let n = self.socket.read(&mut tmp)?;
-                chunk_handler.parse_chunks(&tmp[..n])?;
-                if chunk_handler.is_body_ready() {
-                    if chunk_handler.leftover.len() > 0 {
-                        self.leftover = chunk_handler.leftover;
-                    }
-                    break;
-                }
+            None => None,
+        };
+        self.socket.set_read_timeout(timeout)?;
+        match self.socket.read(buffer) {
+            Ok(n) => Ok(n),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Err(Box::new(HttpError::Error408))
             }
+            Err(e) => Err(Box::new(e)),
         }
-        return Ok(chunk_handler.body);
     }
+}
 
-    fn handle_content_length_body(
-        &mut self,
-        buffer: &[u8],
-        body_length: usize,
-    ) -> Result<Vec<u8>, Box<dyn Error>> {
-        if body_length < buffer.len() {
-            self.leftover = buffer[body_length..].to_vec();
-            Ok(buffer[..body_length].to_vec())
-        } else if body_length == buffer.len() {
-            Ok(buffer.to_vec())
-        } else {
-            let remaining_buffer = self.read_remaining(buffer, body_length)?;
-            self.assemble_buffer_with_remaining(buffer, &remaining_buffer, body_length)
-        }
+/// Set the `Connection` and `Keep-Alive` headers of a response, `served` being the number
+/// of requests answered on the connection including this one.
+pub(crate) fn set_connection_headers(
+    response: &mut Response,
+    keep_alive: bool,
+    served: usize,
+    timeouts: &Timeouts,
+    max_requests: Option<usize>,
+) {
+    if !keep_alive {
+        response.close_connection();
+        return;
     }
-
-    fn read_remaining(
-        &mut self,
-        buffer: &[u8],
-        body_length: usize,
-    ) -> Result<Vec<u8>, Box<dyn Error>> {
-        let remaining_size = body_length - buffer.len();
-        if remaining_size == 0 {
-            return Ok(buffer.to_vec());
-        }
-        let mut remaining_buffer = vec![0u8; remaining_size];
-        self.socket.read_exact(&mut remaining_buffer)?;
-        Ok(remaining_buffer)
-    }
-
-    fn assemble_buffer_with_remaining(
-        &self,
-        buffer: &[u8],
-        remaining_buffer: &[u8],
-        body_length: usize,
-    ) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut body = Vec::with_capacity(body_length);
-        body.extend_from_slice(buffer);
-        body.extend_from_slice(&remaining_buffer);
-        Ok(body)
+    response.set_header("Connection", "keep-alive");
+    let mut parameters = Vec::new();
+    if let Some(idle) = timeouts.idle {
+        parameters.push(format!("timeout={}", idle.as_secs()));
     }
-}
-
-fn get_double_crcn_index(buffer: &[u8]) -> Option<usize> {
-    for (i, _) in buffer.iter().enumerate() {
-        if i + 3 < buffer.len() {
-            if String::from_utf8_lossy(&buffer[i..i + 4]) == "\r\n\r\n" {
-                return Some(i);
-            }
-        } else {
-            break;
-        }
+    if let Some(max) = max_requests {
+        parameters.push(format!("max={}", max - served));
+    }
+    if !parameters.is_empty() {
+        response.set_header("Keep-Alive", &parameters.join(", "));
     }
-    None
 }
 
-fn get_encoding(encoding: &str) -> Option<Encoding> {
-    match encoding.split(" ").next().unwrap().trim() {
-        "gzip" => Some(Encoding::Gzip),
-        "deflate" => Some(Encoding::Deflate),
-        _ => None,
-    }
+fn is_timeout(error: &(dyn Error + 'static)) -> bool {
+    matches!(error.downcast_ref::<HttpError>(), Some(HttpError::Error408))
 }
 
 #[cfg(test)]
 mod test {
     use crate::content_type::ContentType;
     use crate::mock::{TcpStreamMock, CHUNKED, EXPECTED, REGULAR_PACKET};
+    use crate::response_body::ResponseBody;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
 
     use super::*;
 
     fn get_worker(data: &[&[u8]]) -> Worker<TcpStreamMock> {
         let socket = TcpStreamMock::new(data);
-        Worker {
-            peer: "127.0.0.1:8080".to_string(),
-            socket,
-            leftover: vec![0u8; 0],
-        }
+        Worker::new(socket, "127.0.0.1:8080".to_string())
+    }
+
+    fn received(worker: &Worker<TcpStreamMock>) -> String {
+        String::from_utf8_lossy(&worker.socket.lock().receive).into_owned()
+    }
+
+    fn get_tcp_worker(timeouts: Timeouts) -> (Worker<TcpStream>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (socket, peer) = listener.accept().unwrap();
+        let worker = Worker::new(socket, peer.to_string()).with_timeouts(timeouts);
+        (worker, client)
     }
 
     fn handle_client_mock(request: Request) -> Response {
@@ -229,9 +382,9 @@ mod test {
     #[test]
     fn it_should_parse_request_body() {
         let mut worker = get_worker(REGULAR_PACKET);
-        worker.run(handle_client_mock);
+        worker.run(&handle_client_mock);
 
-        let request = String::from_utf8_lossy(&worker.socket.receive);
+        let request = received(&worker);
         let mut request_splits = request.split("\r\n\r\n");
         let _response_header = request_splits.next().unwrap();
         let request_header = request_splits.next().unwrap();
@@ -242,17 +395,17 @@ mod test {
         let expected_body = expected_splits.next().unwrap();
 
         assert_eq!(request_body, expected_body);
-        for header in request_header.split("\r\n").into_iter() {
-            assert!(expected_header.contains(&header));
+        for header in request_header.split("\r\n") {
+            assert!(expected_header.contains(header));
         }
     }
 
     #[test]
     fn it_should_parse_request_chunked_body() {
         let mut worker = get_worker(CHUNKED);
-        worker.run(handle_client_mock);
+        worker.run(&handle_client_mock);
 
-        let request = String::from_utf8_lossy(&worker.socket.receive);
+        let request = received(&worker);
         let mut request_splits = request.split("\r\n\r\n");
         request_splits.next().unwrap();
         request_splits.next().unwrap();
@@ -260,4 +413,376 @@ mod test {
 
         assert_eq!(request_body, "HelloWorldfromthesky");
     }
+
+    #[test]
+    fn it_should_expose_chunked_trailers() {
+        let mut worker = get_worker(&[
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\nb\r\nHello ",
+            b"World\r\n0\r\nChecksum: 42\r\n\r\n",
+        ]);
+        worker.run(&|request: Request| {
+            let checksum = request.trailers.get("Checksum").unwrap_or_default();
+            let body = format!("{} {checksum}", String::from_utf8_lossy(&request.body));
+            Response::new(200, body.into_bytes(), vec![], ContentType::Text)
+        });
+
+        assert!(received(&worker).ends_with("\r\n\r\nHello World 42"));
+    }
+
+    #[test]
+    fn it_should_read_body_with_lowercase_content_length() {
+        let mut worker = get_worker(&[
+            b"POST / HTTP/1.1\r\nhost: localhost\r\ncontent-length: 5\r\nconnection: close\r\n\r\nHello",
+        ]);
+        worker.run(&handle_client_mock);
+
+        let response = received(&worker);
+        assert!(response.ends_with("\r\n\r\nHello"));
+    }
+
+    fn gzip_request(body: &[u8], content_encoding: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        let body = encoder.finish().unwrap();
+        let mut request = format!(
+            "POST / HTTP/1.1\r\nContent-Encoding: {content_encoding}\r\n\
+            Content-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        request.extend_from_slice(&body);
+        request
+    }
+
+    #[test]
+    fn it_should_decode_content_encoding() {
+        let request = gzip_request(b"Hello", "gzip");
+        let mut worker = get_worker(&[&request]);
+        worker.run(&|request: Request| {
+            assert!(!request.headers.contains("Content-Encoding"));
+            Response::new(200, request.body, vec![], ContentType::Text)
+        });
+
+        assert!(received(&worker).ends_with("\r\n\r\nHello"));
+    }
+
+    #[test]
+    fn it_should_reject_unknown_and_oversized_content_encoding() {
+        let request = gzip_request(b"Hello", "gzip, compress");
+        let mut worker = get_worker(&[&request]);
+        worker.run(&handle_client_mock);
+        assert!(received(&worker).starts_with("HTTP/1.1 415"));
+
+        let request = gzip_request(&[0; 10_000], "gzip");
+        let mut worker = get_worker(&[&request]).with_limits(Limits {
+            max_body_size: 1_000,
+            ..Limits::default()
+        });
+        worker.run(&handle_client_mock);
+        assert!(received(&worker).starts_with("HTTP/1.1 413"));
+    }
+
+    #[test]
+    fn it_should_answer_413_on_streamed_gzip_bomb() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0; 100_000]).unwrap();
+        let bomb = encoder.finish().unwrap();
+        let head = format!(
+            "POST / HTTP/1.1\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            bomb.len()
+        );
+        let mut worker = get_worker(&[
+            head.as_bytes(),
+            &bomb,
+            b"GET /next HTTP/1.1\r\nConnection: close\r\n\r\n",
+        ])
+        .with_body_streaming(true)
+        .with_limits(Limits {
+            max_body_size: 1_000,
+            ..Limits::default()
+        });
+        worker.run(&|mut request: Request| {
+            let mut body = Vec::new();
+            match request.body_reader().read_to_end(&mut body) {
+                Ok(_) => Response::new(200, body, vec![], ContentType::Text),
+                Err(e) => handle_error(Box::new(e)),
+            }
+        });
+
+        let response = received(&worker);
+        assert!(response.starts_with("HTTP/1.1 413"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert_eq!(response.matches("HTTP/1.1 ").count(), 1);
+    }
+
+    fn stream_events(_: Request) -> Response {
+        let chunks = vec![b"Hello".to_vec(), b" World".to_vec()];
+        Response::builder().stream(ResponseBody::chunks(chunks))
+    }
+
+    #[test]
+    fn it_should_send_streamed_body_chunked() {
+        let mut worker = get_worker(&[b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"]);
+        worker.run(&stream_events);
+
+        let response = received(&worker);
+        assert!(response.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(response.contains("Connection: keep-alive\r\n"));
+        assert!(response.ends_with("\r\n\r\n5\r\nHello\r\n6\r\n World\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn it_should_close_after_unknown_length_body_for_http_1_0() {
+        let mut worker = get_worker(&[
+            b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+            b"GET / HTTP/1.0\r\n\r\n",
+        ]);
+        worker.run(&stream_events);
+
+        let response = received(&worker);
+        assert!(!response.contains("Transfer-Encoding"));
+        assert!(response.contains("Connection: close\r\n"));
+        assert!(response.ends_with("\r\n\r\nHello World"));
+    }
+
+    #[test]
+    fn it_should_stream_chunked_body_to_handler() {
+        let mut worker = get_worker(CHUNKED).with_body_streaming(true);
+        worker.run(&|mut request: Request| {
+            let mut body = Vec::new();
+            request.body_reader().read_to_end(&mut body).unwrap();
+            Response::new(200, body, vec![], ContentType::Text)
+        });
+
+        let response = received(&worker);
+        assert!(response.ends_with("\r\n\r\nHelloWorldfromthesky"));
+    }
+
+    #[test]
+    fn it_should_drain_unread_body_before_next_request() {
+        let mut worker = get_worker(&[
+            b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nHello",
+            b"World",
+            b"GET /next HTTP/1.1\r\nConnection: close\r\n\r\n",
+        ])
+        .with_body_streaming(true);
+        worker.run(&|request: Request| {
+            Response::new(200, request.uri.into_bytes(), vec![], ContentType::Text)
+        });
+
+        let response = received(&worker);
+        assert_eq!(count_responses(&worker), 2);
+        assert!(response.ends_with("\r\n\r\n/next"));
+    }
+
+    #[test]
+    fn it_should_send_100_continue_before_body() {
+        let mut worker = get_worker(&[
+            b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\nConnection: close\r\n\r\n",
+            b"Hello",
+        ]);
+        worker.run(&handle_client_mock);
+
+        let response = received(&worker);
+        assert!(response.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nHello"));
+    }
+
+    #[test]
+    fn it_should_answer_417_on_unknown_expectation() {
+        let mut worker =
+            get_worker(&[b"POST / HTTP/1.1\r\nExpect: 200-ok\r\nContent-Length: 5\r\n\r\nHello"]);
+        worker.run(&handle_client_mock);
+
+        assert!(received(&worker).starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+    }
+
+    #[test]
+    fn it_should_reject_smuggling_payloads() {
+        let payloads: &[&[u8]] = &[
+            // CL.TE and TE.CL
+            b"POST / HTTP/1.1\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nG",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n8\r\nSMUGGLED\r\n0\r\n\r\n",
+            // Conflicting or malformed Content-Length
+            b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nHello!",
+            b"POST / HTTP/1.1\r\nContent-Length: 5, 6\r\n\r\nHello!",
+            b"POST / HTTP/1.1\r\nContent-Length: +5\r\n\r\nHello",
+            // Obfuscated or non-final chunked
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: xchunked\r\n\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, identity\r\n\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding : chunked\r\n\r\n0\r\n\r\n",
+            b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            // Obsolete line folding, bare LF and bare CR
+            b"POST / HTTP/1.1\r\nTransfer-Encoding:\r\n chunked\r\n\r\n0\r\n\r\n",
+            b"GET / HTTP/1.1\nHost: localhost\n\nGET /admin HTTP/1.1\r\n\r\n",
+            b"GET / HTTP/1.1\r\nHost: localhost\rX: y\r\n\r\n",
+        ];
+        for payload in payloads {
+            let mut worker = get_worker(&[payload, b"GET / HTTP/1.1\r\n\r\n"]);
+            worker.run(&handle_client_mock);
+
+            let response = received(&worker);
+            assert!(
+                response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+                "{}",
+                String::from_utf8_lossy(payload)
+            );
+            assert!(response.contains("Connection: close\r\n"));
+            assert!(!response.contains("200 OK"));
+        }
+    }
+
+    #[test]
+    fn it_should_accept_identical_content_lengths() {
+        let mut worker = get_worker(&[
+            b"POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\nConnection: close\r\n\r\nHello",
+        ]);
+        worker.run(&handle_client_mock);
+
+        assert!(received(&worker).ends_with("\r\n\r\nHello"));
+    }
+
+    #[test]
+    fn it_should_answer_414_on_long_uri() {
+        let limits = Limits {
+            max_uri_length: 8,
+            ..Limits::default()
+        };
+        let mut worker =
+            get_worker(&[b"GET /0123456789", b" HTTP/1.1\r\n\r\n"]).with_limits(limits);
+        worker.run(&handle_client_mock);
+
+        assert!(received(&worker).starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+    }
+
+    #[test]
+    fn it_should_answer_431_on_large_header_block() {
+        let limits = Limits {
+            max_headers: 2,
+            ..Limits::default()
+        };
+        let mut worker =
+            get_worker(&[b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"]).with_limits(limits);
+        worker.run(&handle_client_mock);
+        assert!(received(&worker).starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        let limits = Limits {
+            max_header_size: 32,
+            ..Limits::default()
+        };
+        let mut worker =
+            get_worker(&[b"GET / HTTP/1.1\r\nX-Large: 0123456789012345"]).with_limits(limits);
+        worker.run(&handle_client_mock);
+        assert!(received(&worker).starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+    }
+
+    #[test]
+    fn it_should_answer_408_when_header_times_out() {
+        let (mut worker, mut client) = get_tcp_worker(Timeouts {
+            header: Some(Duration::from_millis(100)),
+            ..Timeouts::default()
+        });
+        client.write_all(b"GET / HTTP/1.1\r\nHost: local").unwrap();
+        worker.run(&handle_client_mock);
+        drop(worker);
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[test]
+    fn it_should_close_idle_connection_silently() {
+        let (mut worker, mut client) = get_tcp_worker(Timeouts {
+            idle: Some(Duration::from_millis(50)),
+            ..Timeouts::default()
+        });
+        worker.run(&handle_client_mock);
+        drop(worker);
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.is_empty());
+    }
+
+    fn count_responses(worker: &Worker<TcpStreamMock>) -> usize {
+        received(worker).matches("HTTP/1.1 200 OK\r\n").count()
+    }
+
+    #[test]
+    fn it_should_close_http_1_0_connection_without_keep_alive() {
+        let mut worker = get_worker(&[b"GET / HTTP/1.0\r\n\r\nGET / HTTP/1.0\r\n\r\n"]);
+        worker.run(&handle_client_mock);
+
+        let response = received(&worker);
+        assert_eq!(count_responses(&worker), 1);
+        assert!(response.contains("Connection: close\r\n"));
+    }
+
+    #[test]
+    fn it_should_keep_http_1_0_connection_with_keep_alive() {
+        let mut worker = get_worker(&[
+            b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\nGET / HTTP/1.0\r\n\r\n",
+        ]);
+        worker.run(&handle_client_mock);
+
+        assert_eq!(count_responses(&worker), 2);
+    }
+
+    #[test]
+    fn it_should_close_when_client_sends_connection_close() {
+        let mut worker =
+            get_worker(&[b"GET / HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n"]);
+        worker.run(&handle_client_mock);
+
+        assert_eq!(count_responses(&worker), 1);
+    }
+
+    #[test]
+    fn it_should_close_when_handler_forces_close() {
+        let mut worker = get_worker(&[b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n"]);
+        worker.run(&|request: Request| {
+            let mut response = handle_client_mock(request);
+            response.close_connection();
+            response
+        });
+
+        assert_eq!(count_responses(&worker), 1);
+    }
+
+    #[test]
+    fn it_should_limit_requests_per_connection() {
+        let mut worker =
+            get_worker(&[b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n"])
+                .with_max_requests(Some(2));
+        worker.run(&handle_client_mock);
+
+        let response = received(&worker);
+        assert_eq!(count_responses(&worker), 2);
+        assert!(response.contains("Keep-Alive: timeout=5, max=1\r\n"));
+        assert!(response.ends_with("Connection: close\r\n\r\nGET / HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn it_should_hand_over_upgraded_connection() {
+        let mut worker = get_worker(&[
+            b"GET /chat HTTP/1.1\r\nUpgrade: websocket\r\n\r\nHello",
+            b" World",
+        ]);
+        worker.run(&|_: Request| {
+            let mut response = Response::new(101, vec![], vec![], ContentType::Text);
+            response.upgrade = Some(Box::new(|socket, leftover| {
+                let mut data = leftover;
+                socket.read_to_end(&mut data).unwrap();
+                socket.write_all(&data).unwrap();
+            }));
+            response
+        });
+
+        let response = received(&worker);
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(!response.contains("Connection: "));
+        assert!(response.ends_with("\r\n\r\nHello World"));
+    }
 }